@@ -4,11 +4,44 @@ use super::bar::GenBuilder as GB;
 use super::tasks::generator::GenType as GT;
 use super::dzen_format::DzenBuilder as DB;
 use super::dzen_format::config::Config;
+use super::logging::LogSink;
+use log::LevelFilter as LF;
 
 pub const FIFO_PATH:   &str = "/tmp/statusbar_fifo";
+// ask the running bar things with `echo exits | socat - UNIX-CONNECT:/tmp/statusbar.sock`
+pub const SOCKET_PATH: &str = "/tmp/statusbar.sock";
+// Prometheus metrics on `unix:<path>` or `<ip>:<port>`, off by default.
+// Anyone who can connect can read them, so prefer a socket like
+// `Some("unix:/run/user/1000/statusbar_metrics.sock")` and try it with
+// `curl --unix-socket /run/user/1000/statusbar_metrics.sock localhost`
+pub const METRICS_ADDR: Option<&str> = None;
 pub const DZEN_FONT:   &str = "Bitstream Vera Sans:pixelsize=14:antialias=true:hinting=true";
+// xft fonts need the lemonbar-xft fork
+pub const LEMONBAR_FONT: &str = DZEN_FONT;
+// whether --i3bar gives i3bar/swaybar Pango markup, needs a pango font in the bar config
+pub const I3BAR_PANGO: bool = true;
+// a core font for the X11 backend, see `xlsfonts`
+pub const X11_FONT:    &str = "-misc-fixed-medium-r-normal--13-120-75-75-c-70-iso10646-1";
 pub const ICON_PATH:   &str = "~/Documents/statusbar/icons";
 pub const SCRIPT_PATH: &str = "~/Documents/statusbar/scripts";
+// keep some generator state in $XDG_STATE_HOME/statusbar/state
+pub const SAVE_STATE:  bool = true;
+
+// change at runtime with `verbosity [<target>] <level|up|down>` on SOCKET_PATH
+pub const LOG_LEVEL:   LF = LF::Info;
+pub const LOG_SINKS:   &[LogSink] = &[
+    LogSink::Stderr,
+    // LogSink::File{path: "~/.local/state/statusbar/log", max_bytes: 1 << 20, keep: 2},
+    // LogSink::Journald,
+];
+// where the logs for stderr go while `--preview` has the terminal
+pub const PREVIEW_LOG: &str = "/tmp/statusbar_preview.log";
+// targets with their own level. A target is a module path prefix, as
+// shown in each log line, so this is per kind of generator and not per
+// generator in the bars.
+pub const LOG_FILTERS: &[(&str, LF)] = &[
+    ("statusbar::tasks::generator::ipgen", LF::Error),
+];
 
 lazy_static::lazy_static! {
     pub static ref THEME: Config<'static> = {
@@ -23,6 +56,7 @@ lazy_static::lazy_static! {
         h.color.insert("blue2",      "#00ace6");
         h.color.insert("darkorange", "#ff8c00");
         h.color.insert("magenta",    "#ff00ff");
+        h.color.insert("stale",      "#808080");
 
         h.icon.insert("battery",     "kanna");
         h.icon.insert("volume",      "sonico");
@@ -54,6 +88,7 @@ pub fn config() -> SB {
                             .argument("pacman.sh"))
                  .add_right(GB::new(GT::ONE)
                             .argument("pulseaudio.py")
+                            .step(5)
                             .prepend(pre_icon("volume")))
                  .add_right(GB::new(GT::DISK)
                             .argument("/,/media/data"))
@@ -62,6 +62,22 @@ def get_volume(core):
     )
     return volume_perc(volumes)
 
+def change_volume(sink, delta):
+    volumes = sink.Get(
+        "org.PulseAudio.Core1.Device",
+        "Volume",
+        dbus_interface="org.freedesktop.DBus.Properties",
+    )
+    delta_raw = round(delta / 100 * 65536)
+    new = [max(0, min(65536, v + delta_raw)) for v in volumes]
+    sink.Set(
+        "org.PulseAudio.Core1.Device",
+        "Volume",
+        dbus.Array(new, signature="u"),
+        dbus_interface="org.freedesktop.DBus.Properties",
+    )
+
+
 def get_name(core):
     return core.Get(
         "org.PulseAudio.Core1.Device",
@@ -160,6 +176,23 @@ def main():
     conn.add_signal_receiver(on_new_sink, signal_name="NewSink")
     conn.add_signal_receiver(on_removed_sink, signal_name="SinkRemoved")
 
+    # Scroll events are sent on stdin when the generator has a step
+    step = int(os.environ.get("STS_STEP") or 0)
+
+    def on_stdin(source, condition):
+        line = source.readline()
+        if not line:
+            return False
+        msg = line.strip()
+        if msg in ("scroll up", "scroll down"):
+            delta = step if msg == "scroll up" else -step
+            for path in states:
+                change_volume(conn.get_object("org.pulseaudio.Server", path), delta)
+        return True
+
+    if step:
+        GLib.io_add_watch(sys.stdin, GLib.IO_IN, on_stdin)
+
     loop = GLib.MainLoop()
     loop.run()
 
@@ -4,6 +4,7 @@ use itertools::Itertools;
 use crate::tasks::generator::*;
 use crate::x;
 use crate::dzen_format::DzenBuilder;
+use crate::tasks::backend::BackendType;
 
 #[derive(Clone,Debug)]
 pub struct BarConfig {
@@ -13,6 +14,9 @@ pub struct BarConfig {
     separator: String,
     padding: usize,
     split: f32,
+    backend: BackendType,
+    record: Option<String>,
+    priorities: HashMap<GenId, i32>,
 
     xinerama: usize,
     output: String,
@@ -50,6 +54,15 @@ impl std::fmt::Display for SetupConfig {
                 if let Some(pre) = &arg.prepend {
                     write!(f, "{:<6}prepend={}\n", "", pre.to_string())?;
                 }
+                if let Some(step) = arg.step {
+                    write!(f, "{:<6}step={}\n", "", step)?;
+                }
+                if arg.restart_on_resume {
+                    write!(f, "{:<6}restart_on_resume\n", "")?;
+                }
+                if let Some(deadline) = arg.deadline {
+                    write!(f, "{:<6}deadline={}\n", "", deadline)?;
+                }
             }
 
             if let Some(name) = self.get_name(id) {
@@ -219,6 +232,9 @@ impl BarConfig {
                     tray: false,
                     padding: 10,
                     split: 0.5,
+                    backend: BackendType::Dzen,
+                    record: None,
+                    priorities: HashMap::new(),
                     output: output,
                     xinerama: xin,
                     rect: rect
@@ -272,9 +288,25 @@ impl BarConfig {
         self.rect.2
     }
 
+    pub fn get_rect(&self) -> x::Rectangle {
+        self.rect
+    }
+
     pub fn wants_tray(&self) -> bool {
         self.tray
     }
+
+    pub fn get_backend(&self) -> BackendType {
+        self.backend
+    }
+
+    pub fn get_record(&self) -> Option<&str> {
+        self.record.as_deref()
+    }
+
+    pub fn get_priority(&self, id: GenId) -> i32 {
+        self.priorities.get(&id).cloned().unwrap_or(0)
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -288,7 +320,9 @@ pub struct SetupBuilder {
     map_other: Option<Box<dyn Fn(String) -> BarBuilder>>,
     global_sep: Option<String>,
     global_pad: Option<usize>,
-    global_split: Option<f32>
+    global_split: Option<f32>,
+    global_backend: Option<BackendType>,
+    record: Option<String>
 }
 
 pub struct BarBuilder {
@@ -298,7 +332,8 @@ pub struct BarBuilder {
     tray: bool,
     sep: Option<String>,
     pad: Option<usize>,
-    split: Option<f32>
+    split: Option<f32>,
+    backend: Option<BackendType>
 }
 
 pub struct GenBuilder {
@@ -306,7 +341,11 @@ pub struct GenBuilder {
     name: Option<String>,
     arg: Option<String>,
     prepend: Option<DzenBuilder<'static>>,
-    timeout: Option<u64>
+    timeout: Option<u64>,
+    step: Option<u64>,
+    restart_on_resume: bool,
+    deadline: Option<u64>,
+    priority: i32
 }
 
 impl SetupBuilder {
@@ -317,7 +356,9 @@ impl SetupBuilder {
             map_other: None,
             global_sep: None,
             global_pad: None,
-            global_split: None
+            global_split: None,
+            global_backend: None,
+            record: None
         }
     }
 
@@ -348,6 +389,17 @@ impl SetupBuilder {
         self
     }
 
+    pub fn backend(mut self, backend: BackendType) -> Self {
+        self.global_backend = Some(backend);
+        self
+    }
+
+    // append everything every bar shows to the file at `path`
+    pub fn record<S: Into<String>>(mut self, path: S) -> Self {
+        self.record = Some(path.into());
+        self
+    }
+
     pub fn build_custom(mut self, xsetup: x::XSetup, prev: Option<&SetupConfig>) -> Result {
         // TODO: handle mirroring of screens
         if let Some(f) = self.map_other {
@@ -365,6 +417,8 @@ impl SetupBuilder {
         let gsep = self.global_sep;
         let gpad = self.global_pad;
         let gsplit = self.global_split;
+        let gbackend = self.global_backend;
+        let record = self.record;
         let mut setup = SetupConfig::new();
         for b in self.bars.into_iter() {
             let mut bar = BarConfig::new(b.output, &xsetup).ok_or("output is not connected")?;
@@ -378,9 +432,19 @@ impl SetupBuilder {
             if let Some(split) = b.split.or_else(|| gsplit) {
                 bar.split = split;
             }
-
-            SetupBuilder::build_side(b.left, &mut setup, |id| bar.add_left(id), prev);
-            SetupBuilder::build_side(b.right, &mut setup, |id| bar.add_right(id), prev);
+            if let Some(backend) = b.backend.or(gbackend) {
+                bar.backend = backend;
+            }
+            bar.record = record.clone();
+
+            SetupBuilder::build_side(b.left, &mut setup, |id, p| {
+                bar.add_left(id);
+                bar.priorities.insert(id, p);
+            }, prev);
+            SetupBuilder::build_side(b.right, &mut setup, |id, p| {
+                bar.add_right(id);
+                bar.priorities.insert(id, p);
+            }, prev);
             setup.add_bar(bar);
         }
 
@@ -397,22 +461,53 @@ impl SetupBuilder {
         self.build_custom(xsetup, Some(prev))
     }
 
+    // For backends that don't need X. Every bar gets `backend` and an
+    // output of its own, and only the bar on `output` is kept if given.
+    pub fn build_headless(mut self, output: Option<&str>, backend: BackendType) -> Result {
+        if let Some(o) = output {
+            self.bars.retain(|b| b.output == o);
+            if self.bars.is_empty() {
+                return Err(format!("there is no bar on {}", o).into());
+            }
+        }
+        self.map_other = None;
+        self.global_backend = Some(backend);
+        for b in self.bars.iter_mut() {
+            b.backend = None;
+        }
+
+        let outputs = self.bars.iter()
+            .enumerate()
+            .map(|(i, b)| (b.output.clone(), i, (0, 0, 0, 0)))
+            .collect();
+        self.build_custom(x::XSetup::new(outputs, None), None)
+    }
+
     fn build_side<F>(
         gens: Vec<GenBuilder>,
         setup: &mut SetupConfig,
         mut bar_add: F,
         prev: Option<&SetupConfig>
     )
-    where F: FnMut(GenId)
+    where F: FnMut(GenId, i32)
     {
         for l in gens.into_iter() {
-            let args = if l.timeout.is_none() && l.arg.is_none() && l.prepend.is_none() {
+            let args = if l.timeout.is_none() && l.arg.is_none() && l.prepend.is_none() && l.step.is_none()
+                && !l.restart_on_resume && l.deadline.is_none()
+            {
                 None
             } else {
-                Some(GenArg{timeout: l.timeout, arg: l.arg, prepend: l.prepend})
+                Some(GenArg{
+                    timeout: l.timeout,
+                    arg: l.arg,
+                    prepend: l.prepend,
+                    step: l.step,
+                    restart_on_resume: l.restart_on_resume,
+                    deadline: l.deadline
+                })
             };
             let id = setup.create_module(l.typ, args, l.name, prev);
-            bar_add(id);
+            bar_add(id, l.priority);
         }
 
     }
@@ -428,7 +523,8 @@ impl BarBuilder {
             tray: false,
             sep: None,
             pad: None,
-            split: None
+            split: None,
+            backend: None
         }
     }
 
@@ -461,6 +557,11 @@ impl BarBuilder {
         self.split = Some(split);
         self
     }
+
+    pub fn backend(mut self, backend: BackendType) -> Self {
+        self.backend = Some(backend);
+        self
+    }
 }
 
 impl GenBuilder {
@@ -471,7 +572,11 @@ impl GenBuilder {
             name: None,
             arg: None,
             prepend: None,
-            timeout: None
+            timeout: None,
+            step: None,
+            restart_on_resume: false,
+            deadline: None,
+            priority: 0
         }
     }
 
@@ -495,4 +600,26 @@ impl GenBuilder {
         self
     }
 
+    pub fn step(mut self, step: u64) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    pub fn restart_on_resume(mut self) -> Self {
+        self.restart_on_resume = true;
+        self
+    }
+
+    pub fn deadline(mut self, secs: u64) -> Self {
+        self.deadline = Some(secs);
+        self
+    }
+
+    // generators with lower priorities are truncated or hidden first
+    // when the bar gets too full
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
 }
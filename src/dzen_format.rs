@@ -2,18 +2,19 @@ pub mod utils;
 pub mod parser;
 pub mod external;
 pub mod config;
+pub mod markup;
 
-use std::collections::VecDeque;
 use std::ops::{Add,Rem};
 use std::fmt;
 use std::borrow::Cow;
 use config::Config;
+use markup::{Markup, Renderer};
 
 #[derive(Clone,PartialEq,Eq,Debug)]
 pub struct DzenBuilder<'a> {
     theme: Option<&'a Config<'a>>,
-    work: VecDeque<Cow<'a, str>>,
-    res: Vec<Cow<'a, str>>
+    work: Vec<Markup>,
+    res: Vec<Markup>
 }
 
 impl<'a> DzenBuilder<'a> {
@@ -22,7 +23,7 @@ impl<'a> DzenBuilder<'a> {
     pub fn new() -> Self {
         DzenBuilder{
             theme: None,
-            work: VecDeque::new(),
+            work: Vec::new(),
             res: Vec::new()
         }
     }
@@ -37,6 +38,10 @@ impl<'a> DzenBuilder<'a> {
         self.add("\n").to_string()
     }
 
+    pub fn into_markup(self) -> Vec<Markup> {
+        self.new_section().res
+    }
+
     pub fn use_theme(mut self, theme: &'a Config<'a>) -> Self {
         self.theme = Some(theme);
         self
@@ -45,107 +50,73 @@ impl<'a> DzenBuilder<'a> {
     // sections ///////////////////////////////////////////////////////////////
     pub fn new_section(mut self) -> Self {
         for w in self.work.drain(..) {
-            self.res.push(w);
+            markup::push(&mut self.res, w);
         }
-        self.work.clear();
         self
     }
 
     pub fn everything(mut self) -> Self {
-        for r in self.res.drain(..).rev() {
-            self.work.push_front(r);
-        }
-        self.res.clear();
-        self
+        let work = std::mem::replace(&mut self.work, std::mem::take(&mut self.res));
+        self.append(work)
     }
 
     // adapters ///////////////////////////////////////////////////////////////
     pub fn append_icon<S>(self, icon: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        let asd = self.icon_strs(icon.into());
-        asd.into_iter().fold(self, |s, a| s.add(a))
+        let ico = self.icon(icon.into());
+        self.append(vec![ico])
     }
 
     pub fn prepend_icon<S>(self, icon: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        let asd = self.icon_strs(icon.into());
-        asd.into_iter().rev().fold(self, |s, a| s.pre(a))
+        let ico = self.icon(icon.into());
+        self.prepend(vec![ico])
     }
 
-    pub fn colorize<S>(self, color: S) -> Self
+    pub fn colorize<S>(mut self, color: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        let c = color.into();
-        let col = self.theme
-            .and_then(|m| m.color.get(c.as_ref()))
-            .map_or_else(|| c, |s| Cow::from(*s));
-
-        self.add("^fg()")
-            .pre(")")
-            .pre(col)
-            .pre("^fg(")
+        let col = self.theme_color(color.into());
+        self.work = vec![Markup::Color(col, std::mem::take(&mut self.work))];
+        self
     }
 
-    pub fn background<S>(self, color: S) -> Self
+    pub fn background<S>(mut self, color: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        let c = color.into();
-        let col = self.theme
-            .and_then(|m| m.color.get(c.as_ref()))
-            .map_or_else(|| c, |s| Cow::from(*s));
-
-        self.add("^bg()")
-            .pre(")")
-            .pre(col)
-            .pre("^bg(")
+        let col = self.theme_color(color.into());
+        self.work = vec![Markup::Background(col, std::mem::take(&mut self.work))];
+        self
     }
 
-    pub fn click<S>(self, button: usize, command: S) -> Self
+    pub fn click<S>(mut self, button: usize, command: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        self.add("^ca()")
-            .pre(")")
-            .pre(command)
-            .pre(", ")
-            .pre(button.to_string())
-            .pre("^ca(")
+        self.work = vec![Markup::Click(button, command.into().into_owned(), std::mem::take(&mut self.work))];
+        self
     }
 
     pub fn position(self, x: isize, y: isize) -> Self {
-        self.pre(")")
-            .pre(y.to_string())
-            .pre(";")
-            .pre(x.to_string())
-            .pre("^pa(")
+        self.prepend(vec![Markup::Position(x, Some(y))])
     }
 
     pub fn position_x(self, x: isize) -> Self {
-        self.pre(")")
-            .pre(x.to_string())
-            .pre("^pa(")
+        self.prepend(vec![Markup::Position(x, None)])
     }
 
     pub fn shift(self, x: isize, y: isize) -> Self {
-        self.pre(")")
-            .pre(y.to_string())
-            .pre(";")
-            .pre(x.to_string())
-            .pre("^p(")
+        self.prepend(vec![Markup::Shift(x, Some(y))])
     }
 
     pub fn lpad(self, x: usize) -> Self {
-        self.pre(")")
-            .pre(x.to_string())
-            .pre("^p(")
+        self.prepend(vec![Markup::Shift(x as isize, None)])
     }
 
     pub fn rpad(self, x: usize) -> Self
     {
-        self.add("^p(")
-            .add(x.to_string())
-            .add(")")
+        self.append(vec![Markup::Shift(x as isize, None)])
     }
 
     // NOTE: Only works if self doesn't contain any tags. bug(?) in dzen
@@ -155,17 +126,31 @@ impl<'a> DzenBuilder<'a> {
     //     self.surround(&["^ba(", width, ",", align, ")"], &[])
     // }
 
-    pub fn add<S>(mut self, s: S) -> Self
+    // `s` is a dzen string
+    pub fn add<S>(self, s: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        self.work.push_back(s.into());
-        self
+        self.append(markup::parse(&s.into()))
     }
 
-    pub fn pre<S>(mut self, s: S) -> Self
+    pub fn pre<S>(self, s: S) -> Self
     where S: Into<Cow<'a, str>>
     {
-        self.work.push_front(s.into());
+        self.prepend(markup::parse(&s.into()))
+    }
+
+    pub fn append(mut self, m: Vec<Markup>) -> Self {
+        for n in m {
+            markup::push(&mut self.work, n);
+        }
+        self
+    }
+
+    pub fn prepend(mut self, mut m: Vec<Markup>) -> Self {
+        for w in self.work.drain(..) {
+            markup::push(&mut m, w);
+        }
+        self.work = m;
         self
     }
 
@@ -193,45 +178,39 @@ impl<'a> DzenBuilder<'a> {
     }
 
     pub fn rect(self, width: usize, height: usize) -> Self {
-        self.add("^r(")
-            .add(width.to_string())
-            .add("x")
-            .add(height.to_string())
-            .add(")")
+        self.append(vec![Markup::Rect(width, height)])
+    }
+
+    fn theme_color(&self, c: Cow<'a, str>) -> String {
+        self.theme
+            .and_then(|m| m.color.get(c.as_ref()))
+            .map_or_else(|| c.into_owned(), |s| s.to_string())
     }
 
-    fn icon_strs(&self, icon: Cow<'a, str>) -> Vec<Cow<'a, str>> {
-        let mut tmp: Vec<Cow<'a, str>> = vec!["^i(".into()];
+    fn icon(&self, icon: Cow<'a, str>) -> Markup {
         let path = crate::config::ICON_PATH;
-        if path.starts_with("~") {
+        let mut p = if let Some(rest) = path.strip_prefix('~') {
             let h = std::env::var("HOME").expect("couldn't get HOME");
-            tmp.push(h.into());
-            tmp.push(path[1..].into());
+            h + rest
         } else {
-            tmp.push(path.into());
-        }
-        tmp.push("/".into());
+            path.to_string()
+        };
+        p.push('/');
 
         let ico = self.theme
             .and_then(|m| m.icon.get(icon.as_ref()))
             .map_or_else(|| icon, |s| Cow::from(*s));
-        tmp.push(ico);
+        p.push_str(&ico);
 
-        tmp.push(".xpm".into());
-        tmp.push(")".into());
-        tmp
+        p.push_str(".xpm");
+        Markup::Icon(p)
     }
 }
 
 impl fmt::Display for DzenBuilder<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for s in self.res.iter() {
-            f.write_str(s)?;
-        }
-        for s in self.work.iter() {
-            f.write_str(s)?;
-        }
-        Ok(())
+        f.write_str(&markup::Dzen.render(&self.res))?;
+        f.write_str(&markup::Dzen.render(&self.work))
     }
 }
 
@@ -263,6 +242,21 @@ impl Rem<String> for DzenBuilder<'_> {
     }
 }
 
+impl<'a> Add<&[Markup]> for DzenBuilder<'a> {
+    type Output = Self;
+    fn add(self, other: &[Markup]) -> Self::Output {
+        self.append(other.to_vec())
+    }
+}
+
+impl<'a> Rem<&[Markup]> for DzenBuilder<'a> {
+    type Output = Self;
+    fn rem(self, other: &[Markup]) -> Self::Output {
+        let e = !self.work.is_empty();
+        self.guard(e, |b| b.append(other.to_vec()))
+    }
+}
+
 impl<'a> From<&'a str> for DzenBuilder<'a> {
     fn from(s: &'a str) -> Self {
         Self::from_str(s)
@@ -274,3 +268,53 @@ impl From<String> for DzenBuilder<'_> {
         Self::from_str(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what the builder wrote when it concatenated dzen strings
+    fn same_as(b: DzenBuilder<'_>, dzen: &str) {
+        assert_eq!(b.to_string(), dzen);
+        assert_eq!(b.into_markup(), markup::parse(dzen));
+    }
+
+    #[test]
+    fn spans() {
+        same_as(DzenBuilder::from_str("50").add("%").colorize("red").background("blue").name_click(1, "cpu"),
+                "^ca(1,echo cpu click 1 >> /tmp/statusbar_fifo)^bg(blue)^fg(red)50%^fg()^bg()^ca()");
+    }
+
+    #[test]
+    fn sections() {
+        let b = DzenBuilder::new()
+            .add("a").colorize("x")
+            .new_section()
+            .add("b").colorize("y")
+            .everything()
+            .lpad(2)
+            .rpad(3);
+        same_as(b, "^p(2)^fg(x)a^fg()^fg(y)b^fg()^p(3)");
+    }
+
+    #[test]
+    fn positions() {
+        same_as(DzenBuilder::from_str("a").shift(1, -2).position_x(5).rect(2, 10),
+                "^pa(5)^p(1;-2)a^r(2x10)");
+        same_as(DzenBuilder::from_str("a").position(3, 4), "^pa(3;4)a");
+    }
+
+    #[test]
+    fn separators() {
+        let b = DzenBuilder::new() % "/" + "a";
+        let b = b % "/" + "^fg(r)b^fg()";
+        same_as(b, "a/^fg(r)b^fg()");
+        let sep = markup::parse("^fg(g)|^fg()");
+        same_as(DzenBuilder::new() % sep.as_slice() + sep.as_slice(), "^fg(g)|^fg()");
+    }
+
+    #[test]
+    fn escaped() {
+        same_as(DzenBuilder::from_str("1^^2").colorize("red"), "^fg(red)1^^2^fg()");
+    }
+}
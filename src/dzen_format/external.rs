@@ -1,26 +1,25 @@
-use crate::dzen_format::parser;
-use std::borrow::Cow;
+use crate::dzen_format::markup::{self, Markup};
 
 // prepend path to all icons and fix colors according to theme
-pub fn fix_dzen_string<S>(s: S) -> String
+pub fn fix_dzen_string<S>(s: S) -> Vec<Markup>
 where S: AsRef<str>
 {
-    let mut p = parser::Parsed::parse(s.as_ref());
-    p.map_tag(|tag, cont| match tag {
-        "i" => {
-            let themed = crate::config::THEME.icon.get(cont).unwrap_or(&cont);
-            let mut pathed = String::new() + crate::config::ICON_PATH + "/" + themed + ".xpm";
-            if pathed.starts_with("~") {
-                pathed.replace_range(..1, &std::env::var("HOME").expect("couldn't get HOME"));
-            }
-            Cow::from(pathed)
-        }
-        "fg" | "bg" => crate::config::THEME.color.get(cont)
-            .map_or_else(
-                || Cow::from(cont),
-                |s| Cow::from(*s)
-            ),
-        _ => Cow::from(cont)
+    let theme_color = |c: &mut String| if let Some(t) = crate::config::THEME.color.get(c.as_str()) {
+        *c = t.to_string();
+    };
+    let mut tree = markup::parse(s.as_ref());
+    markup::walk_mut(&mut tree, &mut |m| match m {
+        Markup::Icon(cont) => {
+            let themed = crate::config::THEME.icon.get(cont.as_str()).unwrap_or(&cont.as_str()).to_string();
+            let pathed = String::new() + crate::config::ICON_PATH + "/" + &themed + ".xpm";
+            *cont = match pathed.strip_prefix('~') {
+                Some(rest) => std::env::var("HOME").expect("couldn't get HOME") + rest,
+                None => pathed,
+            };
+        },
+        Markup::Color(c, _) | Markup::Background(c, _) => theme_color(c),
+        Markup::Tag(t, c) if t == "fg" || t == "bg" => theme_color(c),
+        _ => (),
     });
-    p.to_string()
+    tree
 }
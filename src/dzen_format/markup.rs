@@ -0,0 +1,525 @@
+use std::fmt::Write;
+use crate::config::THEME;
+use super::parser::{Parsed, Token};
+
+// A dzen string as a tree, so that it can be shown by something that
+// isn't dzen. `^fg`, `^bg` and `^ca` become spans around what they
+// colour or make clickable. Arguments are kept exactly as written, so
+// `Dzen.render(&parse(s)) == s` for every `s` that writes a literal `^`
+// as `^^`.
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum Markup {
+    // without dzen's escaping of `^`
+    Text(String),
+    Color(String, Vec<Markup>),
+    Background(String, Vec<Markup>),
+    // the command is everything after the comma, spaces included
+    Click(usize, String, Vec<Markup>),
+    // `^p(x;y)`
+    Shift(isize, Option<isize>),
+    // `^pa(x;y)`
+    Position(isize, Option<isize>),
+    // `^r(wxh)`
+    Rect(usize, usize),
+    Icon(String),
+    // every other tag, and spans that never closed
+    Tag(String, String),
+}
+
+struct Frame {
+    name: String,
+    arg: String,
+    children: Vec<Markup>,
+}
+
+impl Frame {
+    fn close(self) -> Markup {
+        match self.name.as_str() {
+            "fg" => Markup::Color(self.arg, self.children),
+            "bg" => Markup::Background(self.arg, self.children),
+            _ => {
+                let (b, cmd) = parse_click(&self.arg).unwrap();
+                Markup::Click(b, cmd.to_string(), self.children)
+            },
+        }
+    }
+}
+
+// Only what renders back to the same argument is parsed, `^p(+3)`
+// stays a `Tag`.
+fn parse_click(arg: &str) -> Option<(usize, &str)> {
+    let i = arg.find(',')?;
+    let b: usize = arg[..i].parse().ok()?;
+    if b.to_string() != arg[..i] {
+        return None;
+    }
+    Some((b, &arg[i+1..]))
+}
+
+fn parse_xy(arg: &str) -> Option<(isize, Option<isize>)> {
+    let mut it = arg.splitn(2, ';');
+    let x = it.next()?.parse().ok()?;
+    let y = match it.next() {
+        Some(y) => Some(y.parse().ok()?),
+        None => None,
+    };
+    Some((x, y)).filter(|(x, y)| xy(*x, *y) == arg)
+}
+
+fn parse_rect(arg: &str) -> Option<(usize, usize)> {
+    let i = arg.find('x')?;
+    let (w, h) = (arg[..i].parse().ok()?, arg[i+1..].parse().ok()?);
+    Some((w, h)).filter(|(w, h)| format!("{}x{}", w, h) == arg)
+}
+
+fn tag(name: &str, arg: &str) -> Markup {
+    Markup::Tag(name.to_string(), arg.to_string())
+}
+
+pub fn parse(dzen: &str) -> Vec<Markup> {
+    if !dzen.contains('^') {
+        return match dzen {
+            "" => Vec::new(),
+            t => vec![Markup::Text(t.to_string())],
+        };
+    }
+
+    let parsed = Parsed::parse(dzen);
+    let mut root = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    for t in parsed.tokens() {
+        let node = match t {
+            Token::Text(t) => Markup::Text(t.replace("^^", "^")),
+            Token::Tag(name @ "fg", "") | Token::Tag(name @ "bg", "") | Token::Tag(name @ "ca", "") => {
+                match stack.last() {
+                    Some(f) if f.name == name => stack.pop().unwrap().close(),
+                    _ => tag(name, ""),
+                }
+            },
+            Token::Tag(name @ "fg", arg) | Token::Tag(name @ "bg", arg) => {
+                stack.push(Frame{name: name.to_string(), arg: arg.to_string(), children: Vec::new()});
+                continue;
+            },
+            Token::Tag("ca", arg) if parse_click(arg).is_some() => {
+                stack.push(Frame{name: "ca".to_string(), arg: arg.to_string(), children: Vec::new()});
+                continue;
+            },
+            Token::Tag(name @ "p", arg) | Token::Tag(name @ "pa", arg) => match parse_xy(arg) {
+                Some((x, y)) if name == "p" => Markup::Shift(x, y),
+                Some((x, y)) => Markup::Position(x, y),
+                None => tag(name, arg),
+            },
+            Token::Tag("r", arg) => match parse_rect(arg) {
+                Some((w, h)) => Markup::Rect(w, h),
+                None => tag("r", arg),
+            },
+            Token::Tag("i", arg) => Markup::Icon(arg.to_string()),
+            Token::Tag(name, arg) => tag(name, arg),
+        };
+
+        match stack.last_mut() {
+            Some(f) => push(&mut f.children, node),
+            None => push(&mut root, node),
+        }
+    }
+
+    // a span that never closed is only its opening tag
+    while let Some(f) = stack.pop() {
+        let parent = match stack.last_mut() {
+            Some(p) => &mut p.children,
+            None => &mut root,
+        };
+        parent.push(tag(&f.name, &f.arg));
+        for c in f.children {
+            push(parent, c);
+        }
+    }
+    root
+}
+
+// appends `m`, gluing text onto text
+pub fn push(markup: &mut Vec<Markup>, m: Markup) {
+    match (markup.last_mut(), m) {
+        (_, Markup::Text(t)) if t.is_empty() => (),
+        (Some(Markup::Text(last)), Markup::Text(t)) => last.push_str(&t),
+        (_, m) => markup.push(m),
+    }
+}
+
+pub trait Renderer {
+    fn render(&self, markup: &[Markup]) -> String;
+}
+
+fn color(c: &str) -> &str {
+    THEME.color.get(c).unwrap_or(&c)
+}
+
+fn xy(x: isize, y: Option<isize>) -> String {
+    match y {
+        Some(y) => format!("{};{}", x, y),
+        None => x.to_string(),
+    }
+}
+
+// back to what `parse` got
+pub struct Dzen;
+
+impl Dzen {
+    fn write(&self, s: &mut String, markup: &[Markup]) {
+        for m in markup {
+            match m {
+                Markup::Text(t) => s.push_str(&t.replace('^', "^^")),
+                Markup::Color(c, inner) => {
+                    write!(s, "^fg({})", c).unwrap();
+                    self.write(s, inner);
+                    s.push_str("^fg()");
+                },
+                Markup::Background(c, inner) => {
+                    write!(s, "^bg({})", c).unwrap();
+                    self.write(s, inner);
+                    s.push_str("^bg()");
+                },
+                Markup::Click(b, cmd, inner) => {
+                    write!(s, "^ca({},{})", b, cmd).unwrap();
+                    self.write(s, inner);
+                    s.push_str("^ca()");
+                },
+                Markup::Shift(x, y) => write!(s, "^p({})", xy(*x, *y)).unwrap(),
+                Markup::Position(x, y) => write!(s, "^pa({})", xy(*x, *y)).unwrap(),
+                Markup::Rect(w, h) => write!(s, "^r({}x{})", w, h).unwrap(),
+                Markup::Icon(i) => write!(s, "^i({})", i).unwrap(),
+                Markup::Tag(name, arg) => write!(s, "^{}({})", name, arg).unwrap(),
+            }
+        }
+    }
+}
+
+impl Renderer for Dzen {
+    fn render(&self, markup: &[Markup]) -> String {
+        let mut s = String::new();
+        self.write(&mut s, markup);
+        s
+    }
+}
+
+// https://github.com/LemonBoy/bar#formatting
+// no icons, rectangles nor absolute positions
+pub struct Lemonbar;
+
+impl Lemonbar {
+    fn write(&self, s: &mut String, markup: &[Markup]) {
+        for m in markup {
+            match m {
+                Markup::Text(t) => s.push_str(&t.replace('%', "%%")),
+                Markup::Color(c, inner) => {
+                    write!(s, "%{{F{}}}", color(c)).unwrap();
+                    self.write(s, inner);
+                    s.push_str("%{F-}");
+                },
+                Markup::Background(c, inner) => {
+                    write!(s, "%{{B{}}}", color(c)).unwrap();
+                    self.write(s, inner);
+                    s.push_str("%{B-}");
+                },
+                Markup::Click(b, cmd, inner) => {
+                    write!(s, "%{{A{}:{}:}}", b, cmd.replace(':', "\\:")).unwrap();
+                    self.write(s, inner);
+                    s.push_str("%{A}");
+                },
+                // only the x offset
+                Markup::Shift(x, _) => write!(s, "%{{O{}}}", x).unwrap(),
+                Markup::Tag(name, arg) => match (name.as_str(), arg.as_str()) {
+                    ("fg", "") => s.push_str("%{F-}"),
+                    ("bg", "") => s.push_str("%{B-}"),
+                    ("fg", c) => write!(s, "%{{F{}}}", color(c)).unwrap(),
+                    ("bg", c) => write!(s, "%{{B{}}}", color(c)).unwrap(),
+                    _ => (),
+                },
+                Markup::Position(..) | Markup::Rect(..) | Markup::Icon(_) => (),
+            }
+        }
+    }
+}
+
+impl Renderer for Lemonbar {
+    fn render(&self, markup: &[Markup]) -> String {
+        let mut s = String::new();
+        self.write(&mut s, markup);
+        s
+    }
+}
+
+// only the text
+pub struct Plain;
+
+impl Plain {
+    fn write(&self, s: &mut String, markup: &[Markup]) {
+        for m in markup {
+            match m {
+                Markup::Text(t) => s.push_str(t),
+                Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => self.write(s, inner),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Renderer for Plain {
+    fn render(&self, markup: &[Markup]) -> String {
+        let mut s = String::new();
+        self.write(&mut s, markup);
+        s
+    }
+}
+
+// https://docs.gtk.org/Pango/pango_markup.html
+// Colours are flat like in dzen, where closing one goes back to the
+// default and not to the colour around it, so spans are closed and
+// opened again on every change instead of nested. Padding becomes
+// spaces, everything else that isn't text is dropped.
+pub struct Pango;
+
+// about how wide a space is, for turning `^p` into spaces
+const SPACE_PX: isize = 8;
+
+fn escape_pango(s: &str) -> String {
+    let mut e = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => e.push_str("&amp;"),
+            '<' => e.push_str("&lt;"),
+            '>' => e.push_str("&gt;"),
+            '\'' => e.push_str("&apos;"),
+            '"' => e.push_str("&quot;"),
+            c => e.push(c),
+        }
+    }
+    e
+}
+
+#[derive(Clone,PartialEq,Default)]
+struct PangoStyle<'a> {
+    fg: Option<&'a str>,
+    bg: Option<&'a str>,
+}
+
+impl PangoStyle<'_> {
+    fn open(&self, s: &mut String) {
+        if *self == PangoStyle::default() {
+            return;
+        }
+        s.push_str("<span");
+        if let Some(c) = self.fg {
+            write!(s, " foreground=\"{}\"", escape_pango(color(c))).unwrap();
+        }
+        if let Some(c) = self.bg {
+            write!(s, " background=\"{}\"", escape_pango(color(c))).unwrap();
+        }
+        s.push('>');
+    }
+
+    fn close(&self, s: &mut String) {
+        if *self != PangoStyle::default() {
+            s.push_str("</span>");
+        }
+    }
+}
+
+fn nonempty(c: &str) -> Option<&str> {
+    Some(c).filter(|c| !c.is_empty())
+}
+
+impl Pango {
+    // `t` in `style`, `written` is the style of the open span
+    fn text<'a>(&self, s: &mut String, style: &PangoStyle<'a>, written: &mut PangoStyle<'a>, t: &str) {
+        if t.is_empty() {
+            return;
+        }
+        if written != style {
+            written.close(s);
+            style.open(s);
+            *written = style.clone();
+        }
+        s.push_str(&escape_pango(t));
+    }
+}
+
+impl Renderer for Pango {
+    fn render(&self, markup: &[Markup]) -> String {
+        let mut s = String::new();
+        let mut style = PangoStyle::default();
+        let mut written = PangoStyle::default();
+
+        steps(markup, &mut |st| match st {
+            Step::Open(Markup::Color(c, _)) => style.fg = nonempty(c),
+            Step::Open(Markup::Background(c, _)) => style.bg = nonempty(c),
+            Step::Close(Markup::Color(..)) => style.fg = None,
+            Step::Close(Markup::Background(..)) => style.bg = None,
+            Step::Leaf(Markup::Tag(t, c)) if t == "fg" => style.fg = nonempty(c),
+            Step::Leaf(Markup::Tag(t, c)) if t == "bg" => style.bg = nonempty(c),
+            Step::Leaf(Markup::Text(t)) => self.text(&mut s, &style, &mut written, t),
+            Step::Leaf(Markup::Shift(x, _)) if *x > 0 => {
+                let n = std::cmp::max(1, (x + SPACE_PX / 2) / SPACE_PX) as usize;
+                self.text(&mut s, &style, &mut written, &" ".repeat(n));
+            },
+            _ => (),
+        });
+        written.close(&mut s);
+        s
+    }
+}
+
+// calls `f` on every node, parents before their children
+pub fn walk<'a, F>(markup: &'a [Markup], f: &mut F)
+where F: FnMut(&'a Markup)
+{
+    for m in markup {
+        f(m);
+        match m {
+            Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => walk(inner, f),
+            _ => (),
+        }
+    }
+}
+
+// `f` on every node, mutably, parents before their children
+pub fn walk_mut<F>(markup: &mut [Markup], f: &mut F)
+where F: FnMut(&mut Markup)
+{
+    for m in markup {
+        f(m);
+        match m {
+            Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => walk_mut(inner, f),
+            _ => (),
+        }
+    }
+}
+
+// The tree in the order dzen would read it, for whoever draws it
+// themselves.
+pub enum Step<'a> {
+    // a `Color`, `Background` or `Click` begins
+    Open(&'a Markup),
+    // and ends
+    Close(&'a Markup),
+    // anything without children
+    Leaf(&'a Markup),
+}
+
+pub fn steps<'a, F>(markup: &'a [Markup], f: &mut F)
+where F: FnMut(Step<'a>)
+{
+    for m in markup {
+        match m {
+            Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => {
+                f(Step::Open(m));
+                steps(inner, f);
+                f(Step::Close(m));
+            },
+            _ => f(Step::Leaf(m)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Markup::*;
+
+    fn text(t: &str) -> Markup {
+        Text(t.to_string())
+    }
+
+    // parses `dzen` into `tree` and back again
+    fn round_trip(dzen: &str, tree: Vec<Markup>) {
+        assert_eq!(parse(dzen), tree);
+        assert_eq!(Dzen.render(&tree), dzen);
+    }
+
+    #[test]
+    fn colour() {
+        round_trip("a^fg(red)b^fg()c", vec![text("a"), Color("red".to_string(), vec![text("b")]), text("c")]);
+        round_trip("^bg(#112233)x^bg()", vec![Background("#112233".to_string(), vec![text("x")])]);
+        round_trip("^fg(a)^bg(b)x^bg()^fg()",
+                   vec![Color("a".to_string(), vec![Background("b".to_string(), vec![text("x")])])]);
+    }
+
+    #[test]
+    fn click() {
+        round_trip("^ca(1,cmd)x^ca()", vec![Click(1, "cmd".to_string(), vec![text("x")])]);
+        // spaces after the comma are part of the command
+        round_trip("^ca(3, echo a)x^ca()", vec![Click(3, " echo a".to_string(), vec![text("x")])]);
+        // not a button
+        round_trip("^ca(x,cmd)y^ca()", vec![Tag("ca".to_string(), "x,cmd".to_string()), text("y"), Tag("ca".to_string(), "".to_string())]);
+    }
+
+    #[test]
+    fn padding() {
+        round_trip("^p(3)x^p(-2;1)", vec![Shift(3, None), text("x"), Shift(-2, Some(1))]);
+        round_trip("^pa(10)", vec![Position(10, None)]);
+        // would come back as `^p(3)`
+        round_trip("^p(+3)", vec![Tag("p".to_string(), "+3".to_string())]);
+        round_trip("^p(_LEFT)", vec![Tag("p".to_string(), "_LEFT".to_string())]);
+    }
+
+    #[test]
+    fn rect() {
+        round_trip("^r(4x12)", vec![Rect(4, 12)]);
+        round_trip("^r(4x)", vec![Tag("r".to_string(), "4x".to_string())]);
+    }
+
+    #[test]
+    fn icon() {
+        round_trip("^i(/a/b.xpm) x", vec![Icon("/a/b.xpm".to_string()), text(" x")]);
+    }
+
+    #[test]
+    fn unclosed_spans() {
+        round_trip("^fg(red)a^ca(1,c)b",
+                   vec![Tag("fg".to_string(), "red".to_string()), text("a"), Tag("ca".to_string(), "1,c".to_string()), text("b")]);
+        // a lone close
+        round_trip("a^fg()", vec![text("a"), Tag("fg".to_string(), "".to_string())]);
+        // closes only what it matches
+        round_trip("^fg(a)x^bg()y^fg()",
+                   vec![Color("a".to_string(), vec![text("x"), Tag("bg".to_string(), "".to_string()), text("y")])]);
+        assert_eq!(parse("x^fg("), vec![text("x^fg(")]);
+    }
+
+    #[test]
+    fn escaping() {
+        round_trip("a^^fg(b)", vec![text("a^fg(b)")]);
+        round_trip("^^^^", vec![text("^^")]);
+        round_trip("^fg(r)1^^2^fg()", vec![Color("r".to_string(), vec![text("1^2")])]);
+        assert_eq!(Lemonbar.render(&parse("100%")), "100%%");
+        assert_eq!(Plain.render(&parse("^fg(r)a^^b^fg()^p(2)")), "a^b");
+    }
+
+    #[test]
+    fn pango_is_flat() {
+        // `z` has the default colour in dzen
+        let tree = parse("^fg(#ff0000)x^fg(#00ff00)y^fg()z^fg()");
+        assert_eq!(Pango.render(&tree),
+                   "<span foreground=\"#ff0000\">x</span><span foreground=\"#00ff00\">y</span>z");
+        let tree = parse("^bg(#000000)^fg(#ffffff)a<b^fg()c^bg()");
+        assert_eq!(Pango.render(&tree),
+                   "<span foreground=\"#ffffff\" background=\"#000000\">a&lt;b</span><span background=\"#000000\">c</span>");
+    }
+
+    #[test]
+    fn pango_keeps_padding() {
+        assert_eq!(Pango.render(&parse("^p(16)a^p(3)^r(2x2)")), "  a ");
+        assert_eq!(Pango.render(&parse("^fg(#ffffff)^p(8)a^fg()")), "<span foreground=\"#ffffff\"> a</span>");
+    }
+
+    #[test]
+    fn steps_are_flat() {
+        let tree = parse("^fg(a)x^ca(1,c)y^ca()^fg()z");
+        let mut seen = Vec::new();
+        steps(&tree, &mut |s| seen.push(match s {
+            Step::Open(m) => format!("<{}", Dzen.render(std::slice::from_ref(m)).split(')').next().unwrap()),
+            Step::Close(_) => ">".to_string(),
+            Step::Leaf(m) => Dzen.render(std::slice::from_ref(m)),
+        }));
+        assert_eq!(seen, vec!["<^fg(a", "x", "<^ca(1,c", "y", ">", ">", "z"]);
+    }
+}
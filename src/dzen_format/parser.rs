@@ -1,12 +1,22 @@
 use std::borrow::Cow;
 
+lazy_static::lazy_static! {
+    static ref TAG: regex::Regex = regex::Regex::new(r"(^|[^^])(\^[a-z]{1,2}\()").unwrap();
+}
+
+pub enum Token<'b> {
+    Text(&'b str),
+    // the name and argument of a tag, like `fg` and `red` in `^fg(red)`
+    Tag(&'b str, &'b str),
+}
+
 pub struct Parsed<'a> {
     tokens: Vec<Cow<'a, str>>
 }
 
 impl<'a> Parsed<'a> {
     pub fn parse(s: &'a str) -> Self {
-        let tag = regex::Regex::new(r"(^|[^^])(\^[a-z]{1,2}\()").unwrap();
+        let tag = &*TAG;
         let mut v = Vec::new();
         let mut i = 0;
         while let Some(cap) = tag.captures(&s[i..]) {
@@ -15,15 +25,13 @@ impl<'a> Parsed<'a> {
                 v.push(&s[i..i+mat.start()]);
             }
             v.push(&s[i+mat.start()..i+mat.end()]);
-            if mat.end() < s[i..].len() {
-                if let Some(p) = find_end_par(&s[i+mat.end()..]) {
-                    v.push(&s[i+mat.end()..i+mat.end()+p]);
-                    v.push(")");
-                    let par_len = ')'.len_utf8();
-                    i += mat.end()+p+par_len;
-                } else {
-                    i += mat.end();
-                }
+            if let Some(p) = find_end_par(&s[i+mat.end()..]) {
+                v.push(&s[i+mat.end()..i+mat.end()+p]);
+                v.push(")");
+                let par_len = ')'.len_utf8();
+                i += mat.end()+p+par_len;
+            } else {
+                i += mat.end();
             }
         }
 
@@ -36,30 +44,22 @@ impl<'a> Parsed<'a> {
         }
     }
 
-    pub fn map_tag<F,T>(&mut self, f: F) -> &mut Self
-    where F: Fn(&str, &'a str) -> T,
-          T: Into<Cow<'a, str>>
-    {
-        if self.tokens.len() < 3 {
-            return self;
-        }
-
-        for i in 0..(self.tokens.len() - 2) {
-            if self.tokens[i].as_ref().starts_with("^") {
-                if let Cow::Borrowed(c) = self.tokens[i+1] {
-                    let tag_name = &self.tokens[i];
-                    let n = f(&tag_name[1..tag_name.len()-1], c);
-                    self.tokens[i+1] = n.into();
-                } else {
-                    panic!("can't run map_tag twice");
-                }
+    pub fn tokens(&self) -> Vec<Token<'_>> {
+        let mut v = Vec::new();
+        let mut i = 0;
+        while i < self.tokens.len() {
+            let t = self.tokens[i].as_ref();
+            let is_tag = t.starts_with("^") && t.ends_with("(")
+                && self.tokens.get(i+2).map(|p| p.as_ref()) == Some(")");
+            if is_tag {
+                v.push(Token::Tag(&t[1..t.len()-1], self.tokens[i+1].as_ref()));
+                i += 3;
+            } else {
+                v.push(Token::Text(t));
+                i += 1;
             }
         }
-        self
-    }
-
-    pub fn to_string(self) -> String {
-        self.tokens.into_iter().collect()
+        v
     }
 }
 
@@ -9,6 +9,13 @@ impl<'a> DzenBuilder<'a> {
                                    FIFO_PATH))
     }
 
+    // scrolling up sends `scroll up` and scrolling down sends `scroll down`
+    pub fn name_scroll(self, module_name: impl AsRef<str>) -> Self {
+        let name = module_name.as_ref();
+        self.click(4, format!("echo {} scroll up >> {}", name, FIFO_PATH))
+            .click(5, format!("echo {} scroll down >> {}", name, FIFO_PATH))
+    }
+
     pub fn color_step(self, num: i32, steps: &[(i32, &'a str)]) -> Self {
         let mut color = None;
         for (lim, col) in steps.iter() {
@@ -0,0 +1,265 @@
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use crate::config::{LOG_LEVEL, LOG_SINKS, LOG_FILTERS};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const CRATE: &str = env!("CARGO_PKG_NAME");
+
+// where log lines end up
+#[allow(dead_code)]
+pub enum LogSink {
+    Stderr,
+    // starts over in a new file when `max_bytes` is reached, keeping
+    // `keep` old files as `path.1`, `path.2`...
+    File {path: &'static str, max_bytes: u64, keep: usize},
+    // the native journald protocol
+    Journald,
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn new(path: &str, max_bytes: u64, keep: usize) -> Self {
+        let path = if path.starts_with('~') {
+            let home = std::env::var("HOME").unwrap_or_default();
+            PathBuf::from(path.replacen('~', &home, 1))
+        } else {
+            PathBuf::from(path)
+        };
+        RotatingFile {path, max_bytes, keep, file: None, size: 0}
+    }
+
+    fn rotated(&self, i: usize) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(format!(".{}", i));
+        PathBuf::from(p)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for i in (1..self.keep).rev() {
+            let from = self.rotated(i);
+            if from.exists() {
+                fs::rename(from, self.rotated(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.file.is_some() && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            self.size = f.metadata()?.len();
+            self.file = Some(f);
+        }
+        self.file.as_mut().unwrap().write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+enum Sink {
+    Stderr,
+    File(Mutex<RotatingFile>),
+    Journald(Option<UnixDatagram>),
+}
+
+// https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+fn journald_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+fn journald_priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+struct Levels {
+    global: LevelFilter,
+    // target prefixes with their own level
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+    // the longest matching prefix decides, only this crate logs by default
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(t, _)| target.starts_with(t.as_str()))
+            .max_by_key(|(t, _)| t.len())
+            .map(|(_, l)| *l)
+            .unwrap_or(if target.starts_with(CRATE) {self.global} else {LevelFilter::Off})
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, l)| *l).fold(self.global, std::cmp::max)
+    }
+}
+
+struct Logger {
+    sinks: Vec<Sink>,
+    // where `Sink::Stderr` writes instead, while something else has
+    // the terminal
+    diverted: Mutex<Option<RotatingFile>>,
+    levels: RwLock<Levels>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.levels.read().unwrap().level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let msg = record.args().to_string();
+        if record.level() == Level::Error {
+            crate::tasks::record_error(msg.clone());
+        }
+
+        let line = format!("{} - {} - {} - {}\n", Local::now().format("%FT%T%:z"), record.level(), record.target(), msg);
+        for s in self.sinks.iter() {
+            let res = match s {
+                Sink::Stderr => match self.diverted.lock().unwrap().as_mut() {
+                    Some(f) => f.write_line(&line),
+                    None => io::stderr().write_all(line.as_bytes()),
+                },
+                Sink::File(f) => f.lock().unwrap().write_line(&line),
+                Sink::Journald(None) => Ok(()),
+                Sink::Journald(Some(sock)) => {
+                    let mut buf = Vec::new();
+                    journald_field(&mut buf, "PRIORITY", journald_priority(record.level()));
+                    journald_field(&mut buf, "SYSLOG_IDENTIFIER", CRATE);
+                    journald_field(&mut buf, "CODE_MODULE", record.target());
+                    journald_field(&mut buf, "MESSAGE", &msg);
+                    sock.send_to(&buf, JOURNALD_SOCKET).map(|_| ())
+                }
+            };
+            // nowhere to log this, so stderr it is
+            if let Err(e) = res {
+                eprintln!("couldn't write log line because '{}'", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for s in self.sinks.iter() {
+            if let Sink::File(f) = s {
+                if let Some(file) = f.lock().unwrap().file.as_mut() {
+                    let _ = file.flush();
+                }
+            }
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LOGGER: Logger = Logger {
+        sinks: LOG_SINKS.iter().map(|s| match s {
+            LogSink::Stderr => Sink::Stderr,
+            LogSink::File{path, max_bytes, keep} => Sink::File(Mutex::new(RotatingFile::new(path, *max_bytes, *keep))),
+            LogSink::Journald => Sink::Journald(match UnixDatagram::unbound() {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    eprintln!("couldn't create journald socket because '{}'", e);
+                    None
+                }
+            }),
+        }).collect(),
+        diverted: Mutex::new(None),
+        levels: RwLock::new(Levels {
+            global: LOG_LEVEL,
+            targets: LOG_FILTERS.iter().map(|(t, l)| (t.to_string(), *l)).collect(),
+        }),
+    };
+}
+
+pub fn init() -> Result<(), log::SetLoggerError> {
+    log::set_logger(&*LOGGER)?;
+    log::set_max_level(LOGGER.levels.read().unwrap().max());
+    Ok(())
+}
+
+// Logs meant for stderr go to `path` from now on, for when the
+// terminal shows something else.
+pub fn divert_stderr(path: &str) {
+    *LOGGER.diverted.lock().unwrap() = Some(RotatingFile::new(path, 1 << 20, 1));
+}
+
+fn parse_level(s: &str, current: LevelFilter) -> Option<LevelFilter> {
+    const ORDER: [LevelFilter; 6] = [
+        LevelFilter::Off, LevelFilter::Error, LevelFilter::Warn,
+        LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace,
+    ];
+    let i = ORDER.iter().position(|l| *l == current).unwrap();
+    match s {
+        "up" => Some(ORDER[std::cmp::min(i + 1, ORDER.len() - 1)]),
+        "down" => Some(ORDER[i.saturating_sub(1)]),
+        _ => s.parse().ok(),
+    }
+}
+
+// `verbosity [<target>] <level|up|down>`, or just `verbosity` to see
+// the current levels. Targets are module path prefixes, as shown in
+// every log line, so all generators of a kind share one.
+pub fn verbosity_cmd(args: &[&str]) -> String {
+    let mut levels = LOGGER.levels.write().unwrap();
+    match args {
+        [] => (),
+        [level] => match parse_level(level, levels.global) {
+            Some(l) => levels.global = l,
+            None => return format!("unknown level '{}'\n", level),
+        },
+        [target, level] => {
+            let current = levels.level_for(target);
+            let l = match parse_level(level, current) {
+                Some(l) => l,
+                None => return format!("unknown level '{}'\n", level),
+            };
+            levels.targets.retain(|(t, _)| t != target);
+            levels.targets.push((target.to_string(), l));
+        },
+        _ => return "usage: verbosity [<target>] <level|up|down>\n".to_string(),
+    }
+    log::set_max_level(levels.max());
+
+    let mut ans = format!("{}\n", levels.global);
+    for (t, l) in levels.targets.iter() {
+        ans.push_str(&format!("{} {}\n", t, l));
+    }
+    ans
+}
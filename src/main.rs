@@ -4,13 +4,72 @@ mod dzen_format;
 mod x;
 mod config;
 mod kill;
+mod logging;
+mod systemd;
 
 use tokio;
 use core::time::Duration;
 
-use tasks::main_task;
+use tasks::{main_task,ProcessExitReason};
+use tasks::backend::{BackendType,record};
+use bar::SetupConfig;
 
-use stderrlog as SL;
+fn usage() -> ! {
+    eprintln!("usage: statusbar [--i3bar [OUTPUT] | --preview [OUTPUT]] [--record FILE]");
+    eprintln!("       statusbar --replay FILE");
+    std::process::exit(2);
+}
+
+// `--i3bar` talks the i3bar protocol on stdin and stdout instead of
+// spawning bars, showing the bar on OUTPUT. `--preview` shows the bars
+// in the terminal, so logging better go somewhere else. `--replay`
+// doesn't start anything, see `record::replay`.
+fn setup_from_args() -> SetupConfig {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut headless = None;
+    let mut record = None;
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--i3bar" | "--preview" => {
+                let output = match args.peek() {
+                    Some(o) if !o.starts_with("--") => args.next(),
+                    _ => None,
+                };
+                let b = if a == "--i3bar" {BackendType::I3bar} else {BackendType::Preview};
+                if headless.replace((b, output)).is_some() {
+                    usage();
+                }
+            },
+            "--record" => record = Some(args.next().unwrap_or_else(|| usage())),
+            "--replay" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                std::process::exit(record::replay(&path));
+            },
+            _ => usage(),
+        }
+    }
+
+    let mut builder = config::config();
+    if let Some(r) = record {
+        builder = builder.record(r);
+    }
+
+    match headless {
+        None => builder.build().unwrap(),
+        Some((b, output)) => {
+            if b == BackendType::Preview {
+                eprintln!("logging to {} while previewing", config::PREVIEW_LOG);
+                logging::divert_stderr(config::PREVIEW_LOG);
+            }
+            let setup = builder.build_headless(output.as_deref(), b).unwrap();
+            if b == BackendType::I3bar && setup.bars().len() != 1 {
+                eprintln!("there are several bars, choose one with --i3bar OUTPUT");
+                std::process::exit(2);
+            }
+            setup
+        },
+    }
+}
 
 // TODO: få start att acceptera en lista utav GenArgs där alla måste
 // vara likadana förutom prepend som får (borde) vara annorlunda.
@@ -19,23 +78,15 @@ use stderrlog as SL;
 // flera olika ställen med olika prepend. najs om man vill visa tiden
 // på olika skärmar, alla med olika ikoner, utan att behöva spawna en
 // annars identisk generator flera gånger.
-// TODO: använd spawn_local med tanke på att det bara är en thread (basic scheduler)
 // TODO: byt ut named pipe till sockets, eller kanske ha båda?
 // TODO: kunna ändra antalet dzen utan att starta om allting. Typ när
 // en ny skärm kommer in i bilden.
 
 fn main() {
     let reason = {
-        SL::new()
-            .module(std::module_path!())
-            .timestamp(SL::Timestamp::Second)
-            .show_level(true)
-            .color(SL::ColorChoice::Auto)
-            .verbosity(2) // 4 = trace, 0 = error
-            .init()
-            .expect("couldn't start logger");
+        logging::init().expect("couldn't start logger");
 
-        let setup = config::config().build().unwrap();
+        let setup = setup_from_args();
 
         // NOTE: explicitly creating and shutting down a runtime like this
         // is required because of https://github.com/tokio-rs/tokio/issues/2318
@@ -45,7 +96,9 @@ fn main() {
             .build()
             .unwrap();
 
-        let reason = runtime.block_on(main_task::main(setup));
+        // everything runs on this thread, so tasks don't need to be Send
+        let local = tokio::task::LocalSet::new();
+        let reason = local.block_on(&mut runtime, main_task::main(setup));
 
         // NOTE: a non-zero timeout shouldn't be needed because we should
         // exit _only_ if all tasks have already exited, but just to be
@@ -55,5 +108,8 @@ fn main() {
         reason
     };
 
+    if let ProcessExitReason::Failed(_, er) = &reason {
+        log::error!("exiting because of {}", er);
+    }
     std::process::exit(reason.get_exit_code());
 }
@@ -0,0 +1,82 @@
+use nix::unistd::getpid;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+// https://www.freedesktop.org/software/systemd/man/sd_notify.html
+//
+// Tells systemd about our state, like `READY=1`. Does nothing if we
+// weren't started by systemd with `Type=notify`.
+pub fn notify(state: &str) {
+    let path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(p) if !p.is_empty() => p,
+        _ => return,
+    };
+
+    // an address starting with @ is in the abstract namespace
+    let addr = if let Some(name) = path.strip_prefix('@') {
+        SocketAddr::from_abstract_name(name)
+    } else {
+        SocketAddr::from_pathname(&path)
+    };
+
+    let res = addr.and_then(|a| UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &a));
+    if let Err(e) = res {
+        log::warn!("couldn't notify systemd about '{}' at '{}' because '{}'", state, path, e);
+    }
+}
+
+// How often systemd wants `WATCHDOG=1`, if at all.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<i32>().ok()? != getpid().as_raw() {
+            return None;
+        }
+    }
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::tasks::{Exit,Exits,ExitReason,Kind};
+    use crate::tasks::main_task::status;
+
+    fn recv(sock: &UnixDatagram) -> String {
+        let mut buf = [0; 256];
+        let len = sock.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn notifies_a_stand_in_socket() {
+        let path = std::env::temp_dir().join(format!("statusbar-notify-{}", getpid()));
+        let _ = std::fs::remove_file(&path);
+        let sock = UnixDatagram::bind(&path).unwrap();
+        sock.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        std::env::set_var("NOTIFY_SOCKET", &path);
+
+        notify("READY=1");
+        assert_eq!(recv(&sock), "READY=1");
+
+        let exits: Exits = Rc::new(RefCell::new(vec![Exit {
+            kind: Kind::Generator,
+            component: "generator 'cpu'".to_string(),
+            reason: ExitReason::failed("gone"),
+            errors: Vec::new(),
+            time: Local::now(),
+        }]));
+        notify(&status(&exits));
+        assert_eq!(recv(&sock), "STATUS=running, failed: generator 'cpu'");
+
+        std::env::remove_var("NOTIFY_SOCKET");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,28 +1,173 @@
 pub mod generator;
-pub mod dzen;
+pub mod backend;
+pub mod ipc;
+pub mod logind;
 pub mod main_task;
+pub mod metrics;
 pub mod pipo;
+pub mod printer;
+pub mod ready;
+pub mod sampler;
+pub mod state;
+pub mod stats;
+pub mod uevent;
 
-use crate::tasks::generator::GenId;
+use chrono::{DateTime,Local};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
 
+// control messages to the printers, the output of the generators
+// reaches them through `generator::Latest`
 #[derive(Clone,Debug)]
 pub enum Msg {
-    Gen(GenId, String),
     Tray,
+    // every line the pipe got, for recordings
+    Pipo(String),
 }
 
-#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+#[derive(Clone,Debug)]
 pub enum ExitReason {
     Signal,
-    Error,
+    // what went wrong, with whatever caused it as its `source`
+    Error(Rc<dyn Error>),
     Normal,
     NonFatal,
 }
 
+// an error saying what failed, caused by `source` if anything
+#[derive(Debug)]
+struct Failed {
+    what: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.what)
+    }
+}
+
+impl Error for Failed {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|e| e.as_ref() as &(dyn Error + 'static))
+    }
+}
+
+impl ExitReason {
+    // `what` failed because of `source`
+    pub fn error<E>(what: impl Into<String>, source: E) -> Self
+    where E: Into<Box<dyn Error + Send + Sync>>
+    {
+        ExitReason::Error(Rc::new(Failed {what: what.into(), source: Some(source.into())}))
+    }
+
+    // `what` failed on its own
+    pub fn failed(what: impl Into<String>) -> Self {
+        ExitReason::Error(Rc::new(Failed {what: what.into(), source: None}))
+    }
+
+    pub fn is_normal(&self) -> bool {
+        matches!(self, ExitReason::Normal)
+    }
+
+    pub fn is_non_fatal(&self) -> bool {
+        matches!(self, ExitReason::NonFatal)
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::Signal => write!(f, "a signal"),
+            ExitReason::Normal => write!(f, "nothing left to do"),
+            ExitReason::NonFatal => write!(f, "a non-fatal error"),
+            ExitReason::Error(e) => {
+                write!(f, "error '{}'", e)?;
+                let mut cause = e.source();
+                while let Some(c) = cause {
+                    write!(f, " caused by '{}'", c)?;
+                    cause = c.source();
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+// which kind of task exited, decides the exit code
+#[derive(PartialEq,Eq,Clone,Copy,Debug)]
+pub enum Kind {
+    Generator,
+    Bar,
+    // the pipe, the ipc server and so on
+    Service,
+}
+
+tokio::task_local! {
+    // errors turned into `ExitReason`s by the current task
+    static ERRORS: RefCell<Vec<String>>;
+}
+
+// How and when a task exited. `errors` are the errors the task ran
+// into, oldest first.
+#[derive(Clone,Debug)]
+pub struct Exit {
+    pub kind: Kind,
+    pub component: String,
+    pub reason: ExitReason,
+    pub errors: Vec<String>,
+    pub time: DateTime<Local>,
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} exited because of {}", self.time.format("%F %T"), self.component, self.reason)?;
+        for e in self.errors.iter() {
+            write!(f, "\n    {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+// every task that has exited so far
+pub type Exits = Rc<RefCell<Vec<Exit>>>;
+
+// Runs the task `fut` and remembers the errors it logs.
+pub async fn labeled<F>(kind: Kind, component: String, fut: F) -> Exit
+where F: Future<Output = ExitReason>
+{
+    ERRORS.scope(RefCell::new(Vec::new()), async move {
+        let reason = fut.await;
+        Exit {
+            kind,
+            component,
+            reason,
+            errors: ERRORS.with(|e| e.take()),
+            time: Local::now(),
+        }
+    }).await
+}
+
+// Remembers `msg` as an error of the current task, called for every
+// logged error. Tasks not started through `labeled` only get the log.
+pub fn record_error(msg: String) {
+    let _ = ERRORS.try_with(|errs| {
+        if let Ok(mut errs) = errs.try_borrow_mut() {
+            errs.push(msg);
+        }
+    });
+}
+
+// Why the whole process exits. The first failure is kept, since it is
+// what shut everything else down.
 #[derive(Debug)]
 pub enum ProcessExitReason {
     Okay,
-    Error(Vec<ExitReason>),
+    Signal,
+    Failed(Kind, ExitReason),
 }
 
 type PRE = ProcessExitReason;
@@ -33,36 +178,80 @@ impl ProcessExitReason {
         ProcessExitReason::Okay
     }
 
-    pub fn combine(self, reason: ExitReason) -> Self {
-        match (self, reason) {
-            (p, ER::Normal) => p,
-            (p, ER::NonFatal) => p,
-            (PRE::Okay, e) => PRE::Error(vec![e]),
-            (PRE::Error(mut v), e) if !v.contains(&e) => {
-                v.push(e);
-                PRE::Error(v)
-            },
-            (p, _) => p,
+    pub fn combine(self, exit: &Exit) -> Self {
+        match (self, &exit.reason) {
+            (p, ER::Normal) | (p, ER::NonFatal) => p,
+            (p @ PRE::Failed(..), _) => p,
+            (_, ER::Signal) => PRE::Signal,
+            (_, e @ ER::Error(_)) => PRE::Failed(exit.kind, e.clone()),
         }
     }
 
+    // 1 if a bar failed, 2 if stopped by a signal, 3 if a generator
+    // failed and 4 if anything else did
     pub fn get_exit_code(&self) -> i32 {
         match self {
             PRE::Okay => 0,
-            PRE::Error(v) if v.contains(&ER::Error) && v.contains(&ER::Signal) => 3,
-            PRE::Error(v) if v.contains(&ER::Signal) => 2,
-            PRE::Error(v) if v.contains(&ER::Error) => 1,
-            PRE::Error(_) => panic!("invalid state")
+            PRE::Failed(Kind::Bar, _) => 1,
+            PRE::Signal => 2,
+            PRE::Failed(Kind::Generator, _) => 3,
+            PRE::Failed(Kind::Service, _) => 4,
         }
     }
 }
 
 impl<E> From<E> for ExitReason
-where E: std::fmt::Display
+where E: Into<Box<dyn Error + Send + Sync>>
 {
     fn from(e: E) -> Self {
-        // TODO: don't just print
-        log::error!("got error '{}', converted it to ExitReason::Error", e);
-        ExitReason::Error
+        let e: Rc<dyn Error + Send + Sync> = Rc::from(e.into());
+        ExitReason::Error(e)
+    }
+}
+
+// runs `f` the way main does, on one thread
+#[cfg(test)]
+pub fn run_local<F: Future>(f: F) -> F::Output {
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    tokio::task::LocalSet::new().block_on(&mut runtime, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(kind: Kind, reason: ExitReason) -> Exit {
+        Exit {kind, component: "test".to_string(), reason, errors: Vec::new(), time: Local::now()}
+    }
+
+    #[test]
+    fn errors_keep_their_sources() {
+        let io = std::io::Error::other("disk on fire");
+        let er = ExitReason::error("couldn't read the battery", io);
+        assert_eq!(er.to_string(), "error 'couldn't read the battery' caused by 'disk on fire'");
+    }
+
+    #[test]
+    fn first_failure_decides_the_exit_code() {
+        let reason = ProcessExitReason::new()
+            .combine(&exit(Kind::Service, ExitReason::NonFatal))
+            .combine(&exit(Kind::Generator, ExitReason::failed("gone")))
+            .combine(&exit(Kind::Bar, ExitReason::failed("gone too")))
+            .combine(&exit(Kind::Service, ExitReason::Signal));
+        assert_eq!(reason.get_exit_code(), 3);
+    }
+
+    #[test]
+    fn signals_and_normal_exits() {
+        assert_eq!(ProcessExitReason::new().combine(&exit(Kind::Bar, ExitReason::Normal)).get_exit_code(), 0);
+        let reason = ProcessExitReason::new()
+            .combine(&exit(Kind::Service, ExitReason::Signal))
+            .combine(&exit(Kind::Bar, ExitReason::Normal));
+        assert_eq!(reason.get_exit_code(), 2);
+        assert_eq!(reason.combine(&exit(Kind::Bar, ExitReason::failed("gone"))).get_exit_code(), 1);
     }
 }
@@ -0,0 +1,186 @@
+pub mod dzen;
+pub mod fit;
+pub mod lemonbar;
+pub mod i3bar;
+pub mod preview;
+pub mod record;
+pub mod x11;
+
+use async_trait::async_trait;
+use std::borrow::Cow;
+use std::process::Stdio;
+use tokio::io;
+use tokio::process::Command;
+use std::rc::Rc;
+use tokio::sync::{mpsc, Notify};
+use crate::bar::BarConfig;
+use crate::config::{FIFO_PATH,THEME};
+use crate::dzen_format::DzenBuilder;
+use crate::dzen_format::markup::Markup;
+use crate::kill::ChildTerminator;
+use super::stats::Stats;
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+#[allow(dead_code)]
+pub enum BackendType {
+    Dzen,
+    Lemonbar,
+    I3bar,
+    X11,
+    Preview,
+}
+
+// the output of one generator, higher priorities are kept longer when
+// the bar is too narrow
+pub struct Segment<'a> {
+    pub name: &'a str,
+    pub text: Cow<'a, [Markup]>,
+    pub priority: i32,
+}
+
+// Everything one bar shows. Empty outputs are already left out.
+pub struct Line<'a> {
+    pub left: Vec<Segment<'a>>,
+    pub right: Vec<Segment<'a>>,
+    pub separator: &'a [Markup],
+    pub padding: usize,
+}
+
+fn build_side<'a>(segments: &'a [Segment<'a>], sep: &'a [Markup]) -> DzenBuilder<'a> {
+    segments.iter()
+        .fold(DzenBuilder::new(), |b, s| b % sep + s.text.as_ref())
+}
+
+impl Line<'_> {
+    // the left side as one tree
+    pub fn left_side(&self) -> Vec<Markup> {
+        build_side(&self.left, self.separator)
+            .lpad(self.padding)
+            .into_markup()
+    }
+
+    pub fn right_side(&self) -> Vec<Markup> {
+        build_side(&self.right, self.separator)
+            .rpad(self.padding)
+            .into_markup()
+    }
+}
+
+// The program(s) that show one bar. The printer collects the outputs
+// of the generators and hands them over here.
+#[async_trait(?Send)]
+pub trait Backend {
+    async fn spawn(&mut self) -> io::Result<()>;
+    // returns whether anything was redrawn
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool>;
+    // Called once after spawning. Backends that receive clicks
+    // themselves send them here as `name click N`, the same lines the
+    // `^ca` commands write to the pipe.
+    fn handle_clicks(&mut self, _to_pipo: mpsc::Sender<String>) {}
+    // control messages the printer got, like `tray`, `lagged 2` or
+    // `pipo name click 1`
+    fn control(&mut self, _msg: &str) {}
+    fn supports_tray(&self) -> bool {false}
+    // whether the text can be Pango markup
+    fn supports_pango(&self) -> bool {false}
+    // how wide lines can be, if known
+    fn fit(&mut self) -> Option<&fit::Fit> {None}
+    // notified when the room for the line changed, like when tray icons
+    // come and go
+    fn layout_changed(&self) -> Option<Rc<Notify>> {None}
+    // (re)starts the tray after `delay` seconds
+    fn restart_tray(&mut self, _delay: u64) {}
+    async fn teardown(&mut self) {}
+}
+
+// `#rgb`, `#rrggbb` or `#rrrrggggbbbb` as 0xrrggbb, keeping the high
+// bits of every component
+fn parse_hex(c: &str) -> Option<u32> {
+    let hex = c.strip_prefix('#')?;
+    if hex.len() % 3 != 0 || hex.is_empty() {
+        return None;
+    }
+    let n = hex.len() / 3;
+    let mut rgb = 0;
+    for i in 0..3 {
+        let comp = u32::from_str_radix(&hex[i*n..(i+1)*n], 16).ok()?;
+        let comp = if n >= 2 {comp >> (4 * (n - 2))} else {comp * 0x11};
+        rgb = (rgb << 8) | comp;
+    }
+    Some(rgb)
+}
+
+// a hex colour or one of the X colour names used around here
+pub fn parse_rgb(c: &str) -> Option<u32> {
+    if c.starts_with('#') {
+        return parse_hex(c);
+    }
+    match c.to_lowercase().as_str() {
+        "black" => Some(0x000000),
+        "white" => Some(0xffffff),
+        "red" => Some(0xff0000),
+        "green" => Some(0x00ff00),
+        "blue" => Some(0x0000ff),
+        "yellow" => Some(0xffff00),
+        "gray" | "grey" => Some(0xbebebe),
+        _ => None,
+    }
+}
+
+// like `parse_rgb`, but theme colours first
+pub fn theme_rgb(c: &str) -> Option<u32> {
+    parse_rgb(THEME.color.get(c).unwrap_or(&c))
+}
+
+// sends SIGTERM and waits for `c` to exit
+pub async fn stop(mut c: ChildTerminator, what: &str) {
+    if let Err(e) = c.terminate() {
+        log::warn!("couldn't terminate {} '{}'", what, e);
+    }
+
+    if let Err(e) = c.await {
+        log::warn!("couldn't await because '{}'", e);
+    }
+}
+
+// the line a `^ca` command writes to the pipe, if that is all it does
+fn pipe_line(cmd: &str) -> Option<&str> {
+    cmd.strip_prefix("echo ")?
+        .strip_suffix(FIFO_PATH)?
+        .strip_suffix(" >> ")
+}
+
+// Does what dzen would do with the command of a clicked `^ca` area,
+// but lines for the pipe go straight to pipo. Fails if pipo is gone.
+pub async fn run_click(cmd: &str, to_pipo: &mut mpsc::Sender<String>) -> Result<(), ()> {
+    if let Some(line) = pipe_line(cmd) {
+        return to_pipo.send(line.to_string()).await.map_err(|_| ());
+    }
+    // NOTE: tokio reaps it when it is dropped. Our stdin and stdout might
+    // be talking to i3bar or showing the preview.
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn();
+    if let Err(e) = child {
+        log::warn!("couldn't run '{}' because '{}'", cmd, e);
+    }
+    Ok(())
+}
+
+pub fn bar_to_backend(config: &BarConfig, stats: &Stats) -> Box<dyn Backend> {
+    let backend: Box<dyn Backend> = match config.get_backend() {
+        BackendType::Dzen => Box::new(dzen::DzenBackend::new(config.clone(), stats.clone())),
+        BackendType::Lemonbar => Box::new(lemonbar::LemonbarBackend::new(config.clone(), stats.clone())),
+        BackendType::I3bar => Box::new(i3bar::I3barBackend::new(config.clone())),
+        BackendType::X11 => Box::new(x11::X11Backend::new(config.clone(), stats.clone())),
+        BackendType::Preview => Box::new(preview::PreviewBackend::new(config.clone())),
+    };
+
+    match config.get_record() {
+        Some(path) => Box::new(record::Recorder::new(backend, path.to_string(), config.get_output().to_string())),
+        None => backend,
+    }
+}
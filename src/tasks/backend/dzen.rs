@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use tokio;
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::Notify;
+use std::rc::Rc;
+use crate::kill::*;
+use crate::config::*;
+use crate::bar::BarConfig;
+use crate::tasks::stats::Stats;
+use crate::dzen_format::markup::{Dzen, Renderer};
+use super::{Backend,Line,stop};
+use super::fit::{Fit,Space};
+use super::x11::tray_window::TrayWindow;
+
+fn spawn_dzen(xin: &str, al: &str, x: u16, w: u16) -> io::Result<ChildTerminator> {
+    let fg = THEME.color.get("fg").unwrap_or(&"#ffffff");
+    let bg = THEME.color.get("bg").unwrap_or(&"#000000");
+    Command::new("dzen2")
+        .kill_on_drop(false)
+        .stdin(std::process::Stdio::piped())
+        .args(["-fg", fg])
+        .args(["-bg", bg])
+        .args(["-fn", DZEN_FONT])
+        .args(["-h", "26"])
+        .args(["-xs", xin])
+        .args(["-ta", al])
+        .args(["-x", &x.to_string()])
+        .args(["-w", &w.to_string()])
+        .args(["-dock"])
+        .args(["-e", ""])
+        .spawn()
+        .map(ChildTerminator::new)
+}
+
+// writes `line` unless it is already what dzen shows
+async fn write_changed<W>(w: &mut W, line: String, last: &mut String) -> io::Result<bool>
+where W: AsyncWrite + Unpin
+{
+    if line == *last {
+        return Ok(false);
+    }
+    w.write_all(line.as_bytes()).await?;
+    w.write_all(b"\n").await?;
+    *last = line;
+    Ok(true)
+}
+
+// Two dzen2 windows, one for each side, split at `split` of the
+// screen. Clicks run the `^ca` commands, so they never pass through
+// here.
+pub struct DzenBackend {
+    config: BarConfig,
+    dzens: Option<(ChildTerminator, ChildTerminator)>,
+    last_left: String,
+    last_right: String,
+    tray: TrayWindow,
+    fit: Option<Fit>,
+    // room for the right side without the tray
+    right: i32,
+}
+
+impl DzenBackend {
+    pub fn new(config: BarConfig, stats: Stats) -> Self {
+        let tray = TrayWindow::new(config.clone(), stats);
+        DzenBackend {
+            config,
+            dzens: None,
+            last_left: String::new(),
+            last_right: String::new(),
+            tray,
+            fit: None,
+            right: 0,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for DzenBackend {
+    async fn spawn(&mut self) -> io::Result<()> {
+        let config = &self.config;
+        let left_bar_width = ((config.get_screen_width() as f32) * config.get_split()) as u16;
+        let right_bar_width = config.get_screen_width() - left_bar_width;
+        let xin = config.get_xinerama().to_string();
+
+        let l = spawn_dzen(&xin, "l", 0, left_bar_width)?;
+        let r = spawn_dzen(&xin, "r", left_bar_width, right_bar_width)?;
+        self.dzens = Some((l, r));
+
+        // the windows can't be resized, so neither side can borrow from the other
+        self.right = right_bar_width as i32;
+        self.fit = Fit::for_font(DZEN_FONT, Space {
+            left: left_bar_width as i32,
+            right: right_bar_width as i32,
+            shared: false,
+        });
+        Ok(())
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        let (l, r) = match &mut self.dzens {
+            Some(lr) => lr,
+            None => return Ok(false),
+        };
+        let lstdin = l.as_mut_ref().stdin.as_mut().unwrap();
+        let rstdin = r.as_mut_ref().stdin.as_mut().unwrap();
+
+        let (l, r) = tokio::try_join!(
+            write_changed(lstdin, Dzen.render(&line.left_side()), &mut self.last_left),
+            write_changed(rstdin, Dzen.render(&self.tray.pad(line.right_side())), &mut self.last_right)
+        )?;
+        Ok(l || r)
+    }
+
+    fn supports_tray(&self) -> bool {
+        true
+    }
+
+    fn layout_changed(&self) -> Option<Rc<Notify>> {
+        Some(self.tray.layout_changed())
+    }
+
+    // the right side gets whatever the tray doesn't need
+    fn fit(&mut self) -> Option<&Fit> {
+        let tray = self.tray.width() as i32;
+        let fit = self.fit.as_mut()?;
+        fit.space.right = self.right - tray;
+        Some(fit)
+    }
+
+    // the tray is ours, so there is nothing to wait for
+    fn restart_tray(&mut self, _delay: u64) {
+        self.tray.restart();
+    }
+
+    async fn teardown(&mut self) {
+        if let Some((l, r)) = self.dzens.take() {
+            futures::join!(stop(l, "dzen"), stop(r, "dzen"));
+        }
+        self.tray.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::run_local;
+
+    #[test]
+    fn only_changes_are_written() {
+        run_local(async {
+            let mut out = Vec::new();
+            let mut last = String::new();
+            assert!(write_changed(&mut out, "a".to_string(), &mut last).await.unwrap());
+            assert!(!write_changed(&mut out, "a".to_string(), &mut last).await.unwrap());
+            assert!(write_changed(&mut out, "b".to_string(), &mut last).await.unwrap());
+            assert_eq!(out, b"a\nb\n");
+        });
+    }
+}
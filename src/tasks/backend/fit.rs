@@ -0,0 +1,420 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Char2b, ConnectionExt, QueryFontReply};
+use x11rb::rust_connection::RustConnection;
+use crate::dzen_format::markup::{self, Markup, Renderer};
+use super::Line;
+use super::x11::xpm;
+
+const ELLIPSIS: &str = "…";
+
+// fonts are UCS-2 at most
+pub fn to_char2b(s: &str) -> Vec<Char2b> {
+    s.chars()
+        .map(|c| if (c as u32) > 0xffff {'?'} else {c})
+        .map(|c| {
+            let n = c as u32;
+            Char2b {byte1: (n >> 8) as u8, byte2: n as u8}
+        })
+        .collect()
+}
+
+// the widths of every character of a core X font
+#[derive(Clone)]
+pub struct CharWidths(pub QueryFontReply);
+
+impl CharWidths {
+    // https://www.x.org/releases/X11R7.7/doc/xproto/x11protocol.html#requests:QueryFont
+    pub fn char_width(&self, c: &Char2b) -> i32 {
+        let r = &self.0;
+        if r.char_infos.is_empty() {
+            return r.max_bounds.character_width as i32;
+        }
+        let cols = (r.max_char_or_byte2 - r.min_char_or_byte2 + 1) as usize;
+        let b2 = c.byte2 as u16;
+        if c.byte1 < r.min_byte1 || c.byte1 > r.max_byte1
+            || b2 < r.min_char_or_byte2 || b2 > r.max_char_or_byte2
+        {
+            return r.max_bounds.character_width as i32;
+        }
+        let i = (c.byte1 - r.min_byte1) as usize * cols + (b2 - r.min_char_or_byte2) as usize;
+        r.char_infos.get(i).map_or(r.max_bounds.character_width as i32, |ci| ci.character_width as i32)
+    }
+
+    pub fn text_width(&self, s: &[Char2b]) -> i32 {
+        s.iter().map(|c| self.char_width(c)).sum()
+    }
+
+    pub fn str_width(&self, s: &str) -> i32 {
+        self.text_width(&to_char2b(s))
+    }
+}
+
+// The advances of an xft font, read from the file fontconfig picks
+// for it. Advances are rounded to whole pixels like xft does when
+// hinting.
+pub struct XftWidths {
+    data: Vec<u8>,
+    index: u32,
+    // pixels per font unit
+    scale: f32,
+    chars: RefCell<HashMap<char, i32>>,
+}
+
+impl XftWidths {
+    pub fn open(pattern: &str) -> Result<Self, Box<dyn Error>> {
+        let out = std::process::Command::new("fc-match")
+            .arg("--format=%{file}\n%{index}\n%{pixelsize}")
+            .arg(pattern)
+            .output()?;
+        if !out.status.success() {
+            return Err(format!("fc-match failed with {}", out.status).into());
+        }
+        let out = String::from_utf8(out.stdout)?;
+        let mut lines = out.lines();
+        let (file, index, pixels) = match (lines.next(), lines.next(), lines.next()) {
+            (Some(f), Some(i), Some(p)) if !f.is_empty() => (f, i.parse()?, p.parse::<f32>()?),
+            _ => return Err(format!("fontconfig has no file for '{}'", pattern).into()),
+        };
+
+        let data = std::fs::read(file)?;
+        let upem = ttf_parser::Face::from_slice(&data, index)?.units_per_em();
+        Ok(XftWidths {
+            data,
+            index,
+            scale: pixels / upem as f32,
+            chars: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn char_width(&self, c: char) -> i32 {
+        if let Some(w) = self.chars.borrow().get(&c) {
+            return *w;
+        }
+        let w = ttf_parser::Face::from_slice(&self.data, self.index)
+            .ok()
+            .and_then(|f| f.glyph_index(c).or_else(|| f.glyph_index('?')).and_then(|g| f.glyph_hor_advance(g)))
+            .map_or(0, |a| (a as f32 * self.scale).round() as i32);
+        self.chars.borrow_mut().insert(c, w);
+        w
+    }
+
+    pub fn str_width(&self, s: &str) -> i32 {
+        s.chars().map(|c| self.char_width(c)).sum()
+    }
+}
+
+enum Font {
+    Core(CharWidths),
+    Xft(XftWidths),
+}
+
+// How wide markup is when drawn, in pixels.
+pub struct Measure {
+    font: Font,
+    icons: RefCell<HashMap<String, i32>>,
+}
+
+impl Measure {
+    pub fn new(font: CharWidths) -> Self {
+        Measure {
+            font: Font::Core(font),
+            icons: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // `font` as dzen and lemonbar take it, either the XLFD of a core
+    // font or an xft pattern
+    pub fn for_font(font: &str) -> Result<Self, Box<dyn Error>> {
+        if !font.starts_with('-') {
+            let xft = XftWidths::open(font.strip_prefix("xft:").unwrap_or(font))?;
+            return Ok(Measure {
+                font: Font::Xft(xft),
+                icons: RefCell::new(HashMap::new()),
+            });
+        }
+        let (conn, _) = RustConnection::connect(None)?;
+        let f = conn.generate_id()?;
+        conn.open_font(f, font.as_bytes())?.check()?;
+        let reply = conn.query_font(f)?.reply()?;
+        conn.close_font(f)?;
+        Ok(Measure::new(CharWidths(reply)))
+    }
+
+    fn icon_width(&self, path: &str) -> i32 {
+        if let Some(w) = self.icons.borrow().get(path) {
+            return *w;
+        }
+        let w = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|c| xpm::parse(&c))
+            .map_or(0, |i| i.width as i32);
+        self.icons.borrow_mut().insert(path.to_string(), w);
+        w
+    }
+
+    pub fn str_width(&self, s: &str) -> i32 {
+        match &self.font {
+            Font::Core(f) => f.str_width(s),
+            Font::Xft(f) => f.str_width(s),
+        }
+    }
+
+    pub fn width(&self, tree: &[Markup]) -> i32 {
+        let mut w = 0;
+        markup::walk(tree, &mut |m| w += match m {
+            Markup::Text(t) => self.str_width(t),
+            Markup::Shift(x, _) => *x as i32,
+            Markup::Rect(rw, _) => *rw as i32,
+            Markup::Icon(path) => self.icon_width(path),
+            _ => 0,
+        });
+        w
+    }
+}
+
+// The room a bar has for each side. Backends with one window for both
+// sides let one side use what the other doesn't need, as if the split
+// moved.
+#[derive(Clone,Copy,Debug)]
+pub struct Space {
+    pub left: i32,
+    pub right: i32,
+    pub shared: bool,
+}
+
+pub struct Fit {
+    pub measure: Measure,
+    pub space: Space,
+}
+
+impl Fit {
+    // measures with the font the backend draws with
+    pub fn for_font(font: &str, space: Space) -> Option<Self> {
+        match Measure::for_font(font) {
+            Ok(measure) => Some(Fit {measure, space}),
+            Err(e) => {
+                log::error!("couldn't measure the font {} '{}', the bar won't shrink", font, e);
+                None
+            }
+        }
+    }
+}
+
+// removes the last character of the last text
+fn pop_char(tree: &mut [Markup]) -> Option<char> {
+    for m in tree.iter_mut().rev() {
+        let c = match m {
+            Markup::Text(t) => t.pop(),
+            Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => pop_char(inner),
+            _ => None,
+        };
+        if c.is_some() {
+            return c;
+        }
+    }
+    None
+}
+
+// appends `s` to the last text that isn't empty
+fn push_text(tree: &mut [Markup], s: &str) -> bool {
+    for m in tree.iter_mut().rev() {
+        let done = match m {
+            Markup::Text(t) if !t.is_empty() => {
+                t.push_str(s);
+                true
+            },
+            Markup::Color(_, inner) | Markup::Background(_, inner) | Markup::Click(_, _, inner) => push_text(inner, s),
+            _ => false,
+        };
+        if done {
+            return true;
+        }
+    }
+    false
+}
+
+// makes the text of `tree` at least `remove` pixels shorter, ending in an ellipsis
+fn truncate(mut tree: Vec<Markup>, mut remove: i32, measure: &Measure) -> Vec<Markup> {
+    remove += measure.str_width(ELLIPSIS);
+    while remove > 0 {
+        match pop_char(&mut tree) {
+            Some(c) => remove -= measure.str_width(c.encode_utf8(&mut [0; 4])),
+            None => break,
+        }
+    }
+    if !push_text(&mut tree, ELLIPSIS) {
+        tree.push(Markup::Text(ELLIPSIS.to_string()));
+    }
+    tree
+}
+
+// how wide a side with segments `widths` is
+fn side_width(widths: &[i32], sep: i32, padding: usize) -> i32 {
+    let seps = widths.len().saturating_sub(1) as i32;
+    widths.iter().sum::<i32>() + seps * sep + padding as i32
+}
+
+// Makes `line` fit by truncating or dropping its least important
+// segments, the ones closest to the middle of the bar first among
+// equals. Long segments are truncated, short ones dropped. Every
+// segment is measured once, and again only after being truncated.
+pub fn shrink(line: &mut Line<'_>, fit: &Fit) {
+    let Fit {measure, space} = fit;
+    let ell = measure.str_width(ELLIPSIS);
+    let sep = measure.width(line.separator);
+    let mut lwidths: Vec<i32> = line.left.iter().map(|s| measure.width(&s.text)).collect();
+    let mut rwidths: Vec<i32> = line.right.iter().map(|s| measure.width(&s.text)).collect();
+    loop {
+        let lover = side_width(&lwidths, sep, line.padding) - space.left;
+        let rover = side_width(&rwidths, sep, line.padding) - space.right;
+        let (lover, rover) = if space.shared {
+            (lover + rover, lover + rover)
+        } else {
+            (lover, rover)
+        };
+        if lover <= 0 && rover <= 0 {
+            break;
+        }
+
+        let llen = line.left.len();
+        let victim = line.left.iter()
+            .enumerate()
+            .filter(|_| lover > 0)
+            .map(|(i, s)| (s.priority, llen - 1 - i, true, i))
+            .chain(line.right.iter()
+                   .enumerate()
+                   .filter(|_| rover > 0)
+                   .map(|(i, s)| (s.priority, i, false, i)))
+            .min_by_key(|(p, dist, _, _)| (*p, *dist));
+        let (left, i) = match victim {
+            Some((_, _, left, i)) => (left, i),
+            None => break,
+        };
+
+        let (side, widths, over) = if left {
+            (&mut line.left, &mut lwidths, lover)
+        } else {
+            (&mut line.right, &mut rwidths, rover)
+        };
+        let text_width = measure.str_width(&markup::Plain.render(&side[i].text));
+        // something besides the ellipsis should be left
+        if text_width - over - ell >= 2 * ell {
+            log::debug!("truncating {} by {}px", side[i].name, over);
+            let tree = truncate(side[i].text.to_vec(), over, measure);
+            widths[i] = measure.width(&tree);
+            side[i].text = Cow::Owned(tree);
+        } else {
+            log::debug!("dropping {}, it doesn't fit", side[i].name);
+            side.remove(i);
+            widths.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::{Charinfo, FontDraw};
+    use super::super::Segment;
+
+    // every character is 10px wide
+    fn measure() -> Measure {
+        let info = Charinfo {
+            left_side_bearing: 0,
+            right_side_bearing: 10,
+            character_width: 10,
+            ascent: 10,
+            descent: 2,
+            attributes: 0,
+        };
+        Measure::new(CharWidths(QueryFontReply {
+            sequence: 0,
+            length: 0,
+            min_bounds: info,
+            max_bounds: info,
+            min_char_or_byte2: 0,
+            max_char_or_byte2: 0xffff,
+            default_char: 0,
+            draw_direction: FontDraw::LEFT_TO_RIGHT,
+            min_byte1: 0,
+            max_byte1: 0,
+            all_chars_exist: true,
+            font_ascent: 10,
+            font_descent: 2,
+            properties: Vec::new(),
+            char_infos: Vec::new(),
+        }))
+    }
+
+    fn seg<'a>(name: &'a str, text: &str, priority: i32) -> Segment<'a> {
+        Segment {name, text: markup::parse(text).into(), priority}
+    }
+
+    fn names(segs: &[Segment<'_>]) -> Vec<String> {
+        segs.iter()
+            .map(|s| format!("{}:{}", s.name, markup::Plain.render(&s.text)))
+            .collect()
+    }
+
+    // shrinks `left` and `right` to fit `space`
+    fn shrunk(left: Vec<Segment<'_>>, right: Vec<Segment<'_>>, space: Space) -> (Vec<String>, Vec<String>) {
+        let sep = markup::parse("|");
+        let mut line = Line {left, right, separator: &sep, padding: 0};
+        shrink(&mut line, &Fit {measure: measure(), space});
+        (names(&line.left), names(&line.right))
+    }
+
+    fn split(left: i32, right: i32) -> Space {
+        Space {left, right, shared: false}
+    }
+
+    #[test]
+    fn fitting_lines_are_kept() {
+        let (l, r) = shrunk(vec![seg("a", "^fg(red)aa^fg()", 0), seg("b", "bb", 0)],
+                            vec![seg("c", "cc", 0)],
+                            split(50, 20));
+        assert_eq!(l, vec!["a:aa", "b:bb"]);
+        assert_eq!(r, vec!["c:cc"]);
+    }
+
+    #[test]
+    fn lowest_priority_goes_first() {
+        let (l, _) = shrunk(vec![seg("a", "aa", 0), seg("b", "bb", 5)], vec![], split(40, 0));
+        assert_eq!(l, vec!["b:bb"]);
+    }
+
+    #[test]
+    fn closest_to_the_middle_goes_first() {
+        let (l, r) = shrunk(vec![seg("a", "aa", 0), seg("b", "bb", 0)],
+                            vec![seg("c", "cc", 0), seg("d", "dd", 0)],
+                            split(40, 40));
+        assert_eq!(l, vec!["a:aa"]);
+        assert_eq!(r, vec!["d:dd"]);
+    }
+
+    #[test]
+    fn long_segments_are_truncated() {
+        // 200px in 170px, 40px has to go including the ellipsis
+        let (l, _) = shrunk(vec![seg("a", "^fg(red)aaaaaaaaaaaaaaaaaaaa^fg()", 0)], vec![], split(170, 0));
+        assert_eq!(l, vec!["a:aaaaaaaaaaaaaaaa…"]);
+    }
+
+    #[test]
+    fn truncating_then_dropping() {
+        // `a` is too short to truncate so it goes, then `b` is truncated
+        let (l, _) = shrunk(vec![seg("a", "aaaaaa", 0), seg("b", "bbbbbbbbbb", 1)], vec![], split(60, 0));
+        assert_eq!(l, vec!["b:bbbbb…"]);
+    }
+
+    #[test]
+    fn shared_space_is_borrowed() {
+        let space = Space {left: 20, right: 60, shared: true};
+        let (l, r) = shrunk(vec![seg("a", "aaaa", 0)], vec![seg("b", "bb", 0)], space);
+        assert_eq!(l, vec!["a:aaaa"]);
+        assert_eq!(r, vec!["b:bb"]);
+    }
+}
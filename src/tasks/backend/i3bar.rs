@@ -0,0 +1,243 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use tokio;
+use tokio::io::{self, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Stdout};
+use tokio::sync::mpsc;
+use crate::config::{THEME, I3BAR_PANGO};
+use crate::bar::BarConfig;
+use crate::dzen_format::markup::{self, Markup, Renderer};
+use super::{Backend,Line,Segment,run_click};
+
+// the `^ca` areas of every block, as button and command
+type Areas = Rc<RefCell<HashMap<String, Vec<(u64, String)>>>>;
+
+// A block shows the text of a generator in its first colour, unless
+// it can be Pango markup with all colours.
+fn to_block(seg: &Segment<'_>, instance: &str, pango: bool) -> (Value, Vec<(u64, String)>) {
+    let tree = &seg.text;
+    let text = if pango {
+        markup::Pango.render(tree)
+    } else {
+        markup::Plain.render(tree)
+    };
+    let mut color = None;
+    let mut areas = Vec::new();
+    markup::walk(tree, &mut |m| match m {
+        Markup::Color(c, _) if color.is_none() && !pango => {
+            let c = THEME.color.get(c.as_str()).map_or(c.as_str(), |c| *c);
+            // i3bar only knows #rrggbb
+            if c.starts_with('#') {
+                color = Some(c.to_string());
+            }
+        },
+        Markup::Click(b, cmd, _) => areas.push((*b as u64, cmd.trim().to_string())),
+        _ => (),
+    });
+
+    let mut block = json!({
+        "full_text": text,
+        "name": seg.name,
+        "instance": instance,
+        "separator": true,
+    });
+    if let Some(c) = color {
+        block["color"] = Value::from(c);
+    }
+    if pango {
+        block["markup"] = Value::from("pango");
+    }
+    (block, areas)
+}
+
+async fn read_clicks<R>(input: R, areas: Areas, mut to_pipo: mpsc::Sender<String>)
+where R: AsyncRead + Unpin
+{
+    let mut lines = BufReader::new(input).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(l)) => l,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("couldn't read clicks from stdin '{}'", e);
+                break;
+            }
+        };
+
+        // an infinite array of events, one per line
+        let ev = line.trim_start_matches(|c: char| c == '[' || c == ',' || c.is_whitespace());
+        if ev.is_empty() {
+            continue;
+        }
+        let ev: Value = match serde_json::from_str(ev) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("couldn't parse click event '{}'", e);
+                continue;
+            }
+        };
+
+        let (name, button) = match (ev["name"].as_str(), ev["button"].as_u64()) {
+            (Some(n), Some(b)) => (n, b),
+            _ => continue,
+        };
+        let cmd = areas.borrow()
+            .get(name)
+            .and_then(|a| a.iter().find(|(b, _)| *b == button))
+            .map(|(_, c)| c.clone());
+        if let Some(c) = cmd {
+            if run_click(&c, &mut to_pipo).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// https://i3wm.org/docs/i3bar-protocol.html
+//
+// Writes to stdout and reads clicks from stdin, so there can only be
+// one of these.
+pub struct I3barBackend<W = Stdout> {
+    config: BarConfig,
+    stdout: W,
+    last: String,
+    areas: Areas,
+}
+
+impl I3barBackend {
+    pub fn new(config: BarConfig) -> Self {
+        I3barBackend::with_output(config, io::stdout())
+    }
+}
+
+impl<W> I3barBackend<W> {
+    fn with_output(config: BarConfig, stdout: W) -> Self {
+        I3barBackend {
+            config,
+            stdout,
+            last: String::new(),
+            areas: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<W: AsyncWrite + Unpin> Backend for I3barBackend<W> {
+    async fn spawn(&mut self) -> io::Result<()> {
+        let header = json!({"version": 1, "click_events": true});
+        self.stdout.write_all(format!("{}\n[\n", header).as_bytes()).await?;
+        self.stdout.flush().await
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        let instance = self.config.get_output();
+        let pango = self.supports_pango();
+        let mut blocks = Vec::new();
+        let mut areas = HashMap::new();
+        for seg in line.left.iter().chain(line.right.iter()) {
+            let (b, a) = to_block(seg, instance, pango);
+            blocks.push(b);
+            areas.insert(seg.name.to_string(), a);
+        }
+        *self.areas.borrow_mut() = areas;
+
+        let s = Value::from(blocks).to_string();
+        if s == self.last {
+            return Ok(false);
+        }
+        self.stdout.write_all(s.as_bytes()).await?;
+        self.stdout.write_all(b",\n").await?;
+        self.stdout.flush().await?;
+        self.last = s;
+        Ok(true)
+    }
+
+    fn handle_clicks(&mut self, to_pipo: mpsc::Sender<String>) {
+        tokio::task::spawn_local(read_clicks(io::stdin(), self.areas.clone(), to_pipo));
+    }
+
+    fn supports_pango(&self) -> bool {
+        I3BAR_PANGO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::run_local;
+    use crate::x::XSetup;
+
+    fn config() -> BarConfig {
+        let setup = XSetup::new(vec![("X".to_string(), 0, (0, 0, 0, 0))], None);
+        BarConfig::new("X".to_string(), &setup).unwrap()
+    }
+
+    fn seg<'a>(name: &'a str, text: &'a str) -> Segment<'a> {
+        Segment {name, text: markup::parse(text).into(), priority: 0}
+    }
+
+    #[test]
+    fn header_then_one_array_per_line() {
+        let out = run_local(async {
+            let mut b = I3barBackend::with_output(config(), Vec::new());
+            b.spawn().await.unwrap();
+            let line = Line {
+                left: vec![seg("a", "^fg(#ff0000)x^fg()")],
+                right: vec![seg("b", "^ca(1, echo b click 1 >> /tmp/statusbar_fifo)y^ca()")],
+                separator: &markup::parse(" | "),
+                padding: 3,
+            };
+            assert!(b.render(&line).await.unwrap());
+            // nothing changed
+            assert!(!b.render(&line).await.unwrap());
+            String::from_utf8(b.stdout).unwrap()
+        });
+
+        let mut lines = out.lines();
+        let header: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 1);
+        assert_eq!(header["click_events"], true);
+        assert_eq!(lines.next(), Some("["));
+
+        let blocks: Value = serde_json::from_str(lines.next().unwrap().trim_end_matches(',')).unwrap();
+        let blocks = blocks.as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0]["name"], "a");
+        assert_eq!(blocks[0]["instance"], "X");
+        assert_eq!(blocks[1]["name"], "b");
+        assert!(blocks[1]["full_text"].as_str().unwrap().contains('y'));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn clicks_are_routed_to_pipo() {
+        let got = run_local(async {
+            let areas: Areas = Rc::new(RefCell::new(HashMap::new()));
+            let fifo = crate::config::FIFO_PATH;
+            areas.borrow_mut().insert("cpu".to_string(), vec![
+                (1, format!("echo cpu click 1 >> {}", fifo)),
+                (3, format!("echo cpu click 3 >> {}", fifo)),
+            ]);
+
+            let input = concat!(
+                "[\n",
+                "{\"name\":\"cpu\",\"instance\":\"X\",\"button\":3,\"x\":1,\"y\":1}\n",
+                ",{\"name\":\"cpu\",\"instance\":\"X\",\"button\":2,\"x\":1,\"y\":1}\n",
+                ",{\"name\":\"time\",\"instance\":\"X\",\"button\":1,\"x\":1,\"y\":1}\n",
+                ",garbage\n",
+                ",{\"name\":\"cpu\",\"instance\":\"X\",\"button\":1,\"x\":1,\"y\":1}\n",
+            );
+            let (send, mut recv) = mpsc::channel(8);
+            read_clicks(input.as_bytes(), areas, send).await;
+
+            let mut got = Vec::new();
+            while let Some(l) = recv.recv().await {
+                got.push(l);
+            }
+            got
+        });
+        assert_eq!(got, vec!["cpu click 3", "cpu click 1"]);
+    }
+}
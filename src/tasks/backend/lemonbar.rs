@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use tokio;
+use tokio::io::{self, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, Notify};
+use std::process::Stdio;
+use std::rc::Rc;
+use crate::kill::*;
+use crate::config::*;
+use crate::bar::BarConfig;
+use crate::dzen_format::markup::{Lemonbar, Markup, Renderer};
+use crate::tasks::stats::Stats;
+use super::{Backend,Line,stop,run_click};
+use super::x11::tray_window::{self, TrayWindow};
+use super::fit::{Fit,Space};
+
+fn color(c: &str) -> &str {
+    THEME.color.get(c).unwrap_or(&c)
+}
+
+// `-g`, the whole output at its top
+fn geometry(config: &BarConfig) -> String {
+    let (x, y, w, _) = config.get_rect();
+    format!("{}x26+{}+{}", w, x, y)
+}
+
+// one line for lemonbar, leaving `tray` pixels for the icons
+fn compose(left: &[Markup], right: Vec<Markup>, tray: u16) -> String {
+    format!("%{{l}}{}%{{r}}{}", Lemonbar.render(left), Lemonbar.render(&tray_window::pad(right, tray)))
+}
+
+async fn read_clicks<R>(stdout: R, mut to_pipo: mpsc::Sender<String>)
+where R: AsyncRead + Unpin
+{
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(l)) => l,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("couldn't read clicks from lemonbar '{}'", e);
+                break;
+            }
+        };
+
+        // lemonbar prints the command of the clicked area
+        if run_click(&line, &mut to_pipo).await.is_err() {
+            break;
+        }
+    }
+}
+
+// One lemonbar spanning the whole output, with both sides in the same
+// process.
+pub struct LemonbarBackend {
+    config: BarConfig,
+    lemonbar: Option<ChildTerminator>,
+    last: String,
+    tray: TrayWindow,
+    fit: Option<Fit>,
+    // room for the right side without the tray
+    right: i32,
+}
+
+impl LemonbarBackend {
+    pub fn new(config: BarConfig, stats: Stats) -> Self {
+        let tray = TrayWindow::new(config.clone(), stats);
+        LemonbarBackend {
+            config,
+            lemonbar: None,
+            last: String::new(),
+            tray,
+            fit: None,
+            right: 0,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for LemonbarBackend {
+    async fn spawn(&mut self) -> io::Result<()> {
+        let (_, _, w, _) = self.config.get_rect();
+        let c = Command::new("lemonbar")
+            .kill_on_drop(false)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .args(["-g", &geometry(&self.config)])
+            .args(["-F", color("fg")])
+            .args(["-B", color("bg")])
+            .args(["-f", LEMONBAR_FONT])
+            // number of clickable areas
+            .args(["-a", "64"])
+            .args(["-d"])
+            .spawn()?;
+        self.lemonbar = Some(ChildTerminator::new(c));
+
+        let left = (w as f32 * self.config.get_split()) as i32;
+        self.right = w as i32 - left;
+        self.fit = Fit::for_font(LEMONBAR_FONT, Space {left, right: self.right, shared: true});
+        Ok(())
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        let stdin = match &mut self.lemonbar {
+            Some(l) => l.as_mut_ref().stdin.as_mut().unwrap(),
+            None => return Ok(false),
+        };
+
+        let line = compose(&line.left_side(), line.right_side(), self.tray.width());
+        if line == self.last {
+            return Ok(false);
+        }
+        stdin.write_all(line.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        self.last = line;
+        Ok(true)
+    }
+
+    fn handle_clicks(&mut self, to_pipo: mpsc::Sender<String>) {
+        let stdout = self.lemonbar.as_mut().and_then(|l| l.as_mut_ref().stdout.take());
+        if let Some(out) = stdout {
+            tokio::task::spawn_local(read_clicks(out, to_pipo));
+        }
+    }
+
+    fn supports_tray(&self) -> bool {
+        true
+    }
+
+    fn layout_changed(&self) -> Option<Rc<Notify>> {
+        Some(self.tray.layout_changed())
+    }
+
+    // the right side gets whatever the tray doesn't need
+    fn fit(&mut self) -> Option<&Fit> {
+        let tray = self.tray.width() as i32;
+        let fit = self.fit.as_mut()?;
+        fit.space.right = self.right - tray;
+        Some(fit)
+    }
+
+    // the tray is ours, so there is nothing to wait for
+    fn restart_tray(&mut self, _delay: u64) {
+        self.tray.restart();
+    }
+
+    async fn teardown(&mut self) {
+        if let Some(l) = self.lemonbar.take() {
+            stop(l, "lemonbar").await;
+        }
+        self.tray.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::run_local;
+    use crate::x::XSetup;
+
+    fn text(t: &str) -> Markup {
+        Markup::Text(t.to_string())
+    }
+
+    #[test]
+    fn spans_the_output() {
+        let setup = XSetup::new(vec![("X".to_string(), 0, (1920, 0, 1280, 1024))], None);
+        let config = BarConfig::new("X".to_string(), &setup).unwrap();
+        assert_eq!(geometry(&config), "1280x26+1920+0");
+    }
+
+    #[test]
+    fn leaves_room_for_the_tray() {
+        assert_eq!(compose(&[text("a")], vec![text("b")], 0), "%{l}a%{r}b");
+        assert_eq!(compose(&[text("a")], vec![text("b")], 48), "%{l}a%{r}b%{O48}");
+    }
+
+    #[test]
+    fn clicks_go_to_pipo() {
+        let stdout = format!("echo NET-0 click 1 >> {0}\necho TIME-3 scroll up >> {0}\n", FIFO_PATH);
+        let (tx, mut rx) = mpsc::channel(8);
+        run_local(read_clicks(stdout.as_bytes(), tx));
+
+        assert_eq!(rx.try_recv().ok().as_deref(), Some("NET-0 click 1"));
+        assert_eq!(rx.try_recv().ok().as_deref(), Some("TIME-3 scroll up"));
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,445 @@
+use async_trait::async_trait;
+use nix::libc;
+use nix::sys::termios::{self, LocalFlags, OutputFlags, SetArg};
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Once;
+use tokio;
+use tokio::io::{self, AsyncReadExt};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use crate::bar::BarConfig;
+use crate::dzen_format::markup::{self, Markup, Step};
+use super::{Backend,Line,run_click,theme_rgb};
+
+// dzen fonts are about this wide, for turning `^p` into columns
+const COLUMN_PX: i32 = 8;
+const HELP: &str = "h/l segment, j/k bar, 1-5 click, q quit";
+
+#[derive(Clone,Copy,PartialEq,Eq)]
+struct Style {
+    fg: Option<u32>,
+    bg: Option<u32>,
+    selected: bool,
+}
+
+impl Style {
+    fn base() -> Self {
+        Style {
+            fg: theme_rgb("fg"),
+            bg: theme_rgb("bg"),
+            selected: false,
+        }
+    }
+
+    // https://en.wikipedia.org/wiki/ANSI_escape_code#24-bit
+    fn escape(&self) -> String {
+        let mut s = String::from("\x1b[0m");
+        if let Some(c) = self.fg {
+            s += &format!("\x1b[38;2;{};{};{}m", c >> 16, (c >> 8) & 0xff, c & 0xff);
+        }
+        if let Some(c) = self.bg {
+            s += &format!("\x1b[48;2;{};{};{}m", c >> 16, (c >> 8) & 0xff, c & 0xff);
+        }
+        if self.selected {
+            s += "\x1b[7m";
+        }
+        s
+    }
+}
+
+type Cells = Vec<(char, Style)>;
+
+fn columns(px: i32) -> usize {
+    if px <= 0 {
+        0
+    } else {
+        std::cmp::max(1, (px + COLUMN_PX / 2) / COLUMN_PX) as usize
+    }
+}
+
+// the cells of `tree` and its `^ca` areas, as button and command
+fn to_cells(tree: &[Markup], selected: bool) -> (Cells, Vec<(u64, String)>) {
+    let base = Style {selected, ..Style::base()};
+    let mut style = base;
+    let mut cells = Vec::new();
+    let mut areas = Vec::new();
+    let mut push = |s: &str, style: Style| cells.extend(s.chars().map(|c| (c, style)));
+
+    // like dzen, closing a colour goes back to the default one
+    markup::steps(tree, &mut |s| match s {
+        Step::Open(Markup::Color(c, _)) => style.fg = theme_rgb(c).or(base.fg),
+        Step::Open(Markup::Background(c, _)) => style.bg = theme_rgb(c).or(base.bg),
+        Step::Open(Markup::Click(b, cmd, _)) => areas.push((*b as u64, cmd.trim().to_string())),
+        Step::Close(Markup::Color(..)) => style.fg = base.fg,
+        Step::Close(Markup::Background(..)) => style.bg = base.bg,
+        Step::Leaf(Markup::Text(t)) => push(t, style),
+        Step::Leaf(Markup::Tag(t, c)) if t == "fg" => style.fg = theme_rgb(c).or(base.fg),
+        Step::Leaf(Markup::Tag(t, c)) if t == "bg" => style.bg = theme_rgb(c).or(base.bg),
+        Step::Leaf(Markup::Shift(px, _)) => push(&" ".repeat(columns(*px as i32)), style),
+        Step::Leaf(Markup::Rect(w, _)) => {
+            let c = if *w <= 4 {"│"} else {"█"};
+            push(&c.repeat(columns(*w as i32)), style);
+        },
+        // the name of the icon file
+        Step::Leaf(Markup::Icon(path)) => {
+            let name = Path::new(path).file_stem().map_or("?".into(), |s| s.to_string_lossy());
+            push(&format!("[{}]", name), style);
+        },
+        _ => (),
+    });
+    (cells, areas)
+}
+
+struct Segment {
+    text: Vec<Markup>,
+    areas: Vec<(u64, String)>,
+}
+
+#[derive(Default)]
+struct Row {
+    left: Vec<Segment>,
+    right: Vec<Segment>,
+    separator: Vec<Markup>,
+    padding: usize,
+}
+
+impl Row {
+    fn segments(&self) -> impl Iterator<Item = &Segment> {
+        self.left.iter().chain(self.right.iter())
+    }
+
+    fn side(&self, segs: &[Segment], offset: usize, selected: Option<usize>) -> Cells {
+        let mut cells = Vec::new();
+        for (i, s) in segs.iter().enumerate() {
+            if i > 0 {
+                cells.extend(to_cells(&self.separator, false).0);
+            }
+            cells.extend(to_cells(&s.text, selected == Some(offset + i)).0);
+        }
+        cells
+    }
+
+    // left and right aligned at `width`, the right side is cut first
+    fn cells(&self, width: usize, selected: Option<usize>) -> Cells {
+        let pad = (' ', Style::base());
+        let mut cells = vec![pad; self.padding];
+        cells.extend(self.side(&self.left, 0, selected));
+        let mut right = self.side(&self.right, self.left.len(), selected);
+        right.extend(vec![pad; self.padding]);
+
+        let fill = width.saturating_sub(cells.len() + right.len());
+        cells.extend(vec![pad; fill]);
+        cells.extend(right);
+        cells.truncate(width);
+        cells
+    }
+}
+
+// the terminal as it was before raw mode, if it is one
+fn restore(saved: Option<&libc::termios>) {
+    if let Some(t) = saved {
+        unsafe {libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, t)};
+    }
+    print!("\x1b[?25h\x1b[?1049l");
+    let _ = std::io::stdout().flush();
+}
+
+// Raw mode and the alternate screen until dropped. Panics restore the
+// terminal too, or their message would be lost with the screen.
+struct RawMode {
+    saved: Option<libc::termios>,
+}
+
+impl RawMode {
+    // but with output processing and signals kept
+    fn enter() -> Self {
+        let saved = match termios::tcgetattr(libc::STDIN_FILENO) {
+            Ok(saved) => {
+                let mut raw = saved.clone();
+                termios::cfmakeraw(&mut raw);
+                raw.output_flags |= OutputFlags::OPOST;
+                raw.local_flags |= LocalFlags::ISIG;
+                if let Err(e) = termios::tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &raw) {
+                    log::warn!("couldn't enter raw mode '{}'", e);
+                }
+                Some(libc::termios::from(saved))
+            },
+            Err(e) => {
+                log::warn!("stdin is not a terminal, keys won't work '{}'", e);
+                None
+            },
+        };
+
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let prev = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore(saved.as_ref());
+                prev(info);
+            }));
+        });
+
+        // alternate screen without a cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        let _ = std::io::stdout().flush();
+        RawMode {saved}
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        restore(self.saved.as_ref());
+    }
+}
+
+#[derive(Default)]
+struct Screen {
+    rows: Vec<Row>,
+    // the segment the keys act on
+    bar: usize,
+    seg: usize,
+    raw: Option<RawMode>,
+    reading: bool,
+    open: usize,
+}
+
+thread_local! {
+    static SCREEN: RefCell<Screen> = RefCell::new(Screen::default());
+}
+
+fn term_width() -> usize {
+    let mut ws: libc::winsize = unsafe {std::mem::zeroed()};
+    let r = unsafe {libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws)};
+    if r == 0 && ws.ws_col > 0 {ws.ws_col as usize} else {80}
+}
+
+impl Screen {
+    fn draw(&self) {
+        let width = term_width();
+        let mut out = String::from("\x1b[H");
+        for (i, row) in self.rows.iter().enumerate() {
+            let selected = if i == self.bar {Some(self.seg)} else {None};
+            let mut last = None;
+            for (c, style) in row.cells(width, selected) {
+                if last != Some(style) {
+                    out += &style.escape();
+                    last = Some(style);
+                }
+                out.push(c);
+            }
+            out += "\x1b[0m\x1b[K\n";
+        }
+        out += "\x1b[2m";
+        out += HELP;
+        out += "\x1b[0m\x1b[K\x1b[J";
+
+        let mut stdout = std::io::stdout();
+        if let Err(e) = stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()) {
+            log::warn!("couldn't draw preview '{}'", e);
+        }
+    }
+
+    fn select(&mut self, dbar: isize, dseg: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let bars = self.rows.len() as isize;
+        self.bar = (self.bar as isize + dbar).rem_euclid(bars) as usize;
+        let segs = self.rows[self.bar].segments().count() as isize;
+        self.seg = if segs == 0 {0} else {(self.seg as isize + dseg).clamp(0, segs - 1) as usize};
+        self.draw();
+    }
+
+    fn click(&self, button: u64) -> Option<String> {
+        self.rows.get(self.bar)?
+            .segments()
+            .nth(self.seg)?
+            .areas.iter()
+            .find(|(b, _)| *b == button)
+            .map(|(_, c)| c.clone())
+    }
+
+    fn open_terminal(&mut self) {
+        self.raw = Some(RawMode::enter());
+        tokio::task::spawn_local(redraw_on_resize());
+    }
+
+    fn close_terminal(&mut self) {
+        self.raw = None;
+    }
+}
+
+// until the terminal is closed
+async fn redraw_on_resize() {
+    let mut resized = match signal(SignalKind::window_change()) {
+        Ok(s) => s,
+        Err(e) => {
+            log::warn!("won't notice the terminal resizing '{}'", e);
+            return;
+        }
+    };
+    while resized.recv().await.is_some() {
+        let open = SCREEN.with(|s| {
+            let s = s.borrow();
+            if s.raw.is_some() {
+                s.draw();
+            }
+            s.raw.is_some()
+        });
+        if !open {
+            break;
+        }
+    }
+}
+
+enum Key {
+    Select(isize, isize),
+    Click(u64),
+    Quit,
+}
+
+fn parse_keys(buf: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < buf.len() {
+        let k = match buf[i] {
+            b'h' => Key::Select(0, -1),
+            b'l' => Key::Select(0, 1),
+            b'j' => Key::Select(1, 0),
+            b'k' => Key::Select(-1, 0),
+            b @ b'1'..=b'5' => Key::Click((b - b'0') as u64),
+            b'q' => Key::Quit,
+            // arrow keys
+            0x1b if buf.get(i+1) == Some(&b'[') && i + 2 < buf.len() => {
+                i += 2;
+                match buf[i] {
+                    b'A' => Key::Select(-1, 0),
+                    b'B' => Key::Select(1, 0),
+                    b'C' => Key::Select(0, 1),
+                    b'D' => Key::Select(0, -1),
+                    _ => {
+                        i += 1;
+                        continue;
+                    },
+                }
+            },
+            _ => {
+                i += 1;
+                continue;
+            },
+        };
+        keys.push(k);
+        i += 1;
+    }
+    keys
+}
+
+async fn read_keys(mut to_pipo: mpsc::Sender<String>) {
+    let mut stdin = io::stdin();
+    let mut buf = [0; 64];
+    loop {
+        let n = match stdin.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                log::warn!("couldn't read keys '{}'", e);
+                break;
+            }
+        };
+
+        for k in parse_keys(&buf[..n]) {
+            let res = match k {
+                Key::Select(dbar, dseg) => {
+                    SCREEN.with(|s| s.borrow_mut().select(dbar, dseg));
+                    Ok(())
+                },
+                Key::Click(b) => match SCREEN.with(|s| s.borrow().click(b)) {
+                    Some(c) => run_click(&c, &mut to_pipo).await,
+                    None => Ok(()),
+                },
+                Key::Quit => to_pipo.send("EXIT".to_string()).await.map_err(|_| ()),
+            };
+            if res.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Every bar as one line of the terminal, the keys act on one segment
+// at a time.
+pub struct PreviewBackend {
+    row: usize,
+    last: (Vec<Markup>, Vec<Markup>),
+}
+
+impl PreviewBackend {
+    pub fn new(_config: BarConfig) -> Self {
+        PreviewBackend {
+            row: 0,
+            last: (Vec::new(), Vec::new()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for PreviewBackend {
+    async fn spawn(&mut self) -> io::Result<()> {
+        self.row = SCREEN.with(|s| {
+            let mut s = s.borrow_mut();
+            if s.open == 0 {
+                s.open_terminal();
+            }
+            s.open += 1;
+            s.rows.push(Row::default());
+            s.rows.len() - 1
+        });
+        Ok(())
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        let sides = (line.left_side(), line.right_side());
+        if sides == self.last {
+            return Ok(false);
+        }
+        self.last = sides;
+
+        let segments = |segs: &[super::Segment<'_>]| segs.iter()
+            .map(|s| Segment {
+                text: s.text.to_vec(),
+                areas: to_cells(&s.text, false).1,
+            })
+            .collect();
+        let row = Row {
+            left: segments(&line.left),
+            right: segments(&line.right),
+            separator: line.separator.to_vec(),
+            padding: columns(line.padding as i32),
+        };
+        SCREEN.with(|s| {
+            let mut s = s.borrow_mut();
+            s.rows[self.row] = row;
+            s.draw();
+        });
+        Ok(true)
+    }
+
+    fn handle_clicks(&mut self, to_pipo: mpsc::Sender<String>) {
+        let first = SCREEN.with(|s| !std::mem::replace(&mut s.borrow_mut().reading, true));
+        if first {
+            tokio::task::spawn_local(read_keys(to_pipo));
+        }
+    }
+
+    async fn teardown(&mut self) {
+        SCREEN.with(|s| {
+            let mut s = s.borrow_mut();
+            s.open -= 1;
+            if s.open == 0 {
+                s.close_terminal();
+            }
+        });
+    }
+}
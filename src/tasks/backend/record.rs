@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io;
+use tokio::sync::mpsc;
+use crate::dzen_format::markup::{self, Dzen, Markup, Renderer};
+use super::fit::Fit;
+use super::{Backend,Line,Segment};
+
+// One JSON object per line, either
+// `{"t": ms, "bar": output, "line": {...}, "composed": {"left": .., "right": ..}}`
+// for every line a bar got, or `{"t": ms, "bar": output, "msg": ..}` for
+// control messages like tray restarts and every line of the pipe.
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn segments_to_json(segs: &[Segment<'_>]) -> Value {
+    segs.iter()
+        .map(|s| json!({"name": s.name, "text": Dzen.render(&s.text), "priority": s.priority}))
+        .collect()
+}
+
+fn line_to_json(line: &Line<'_>) -> Value {
+    json!({
+        "left": segments_to_json(&line.left),
+        "right": segments_to_json(&line.right),
+        "separator": Dzen.render(line.separator),
+        "padding": line.padding,
+    })
+}
+
+fn composed(line: &Line<'_>) -> Value {
+    json!({"left": Dzen.render(&line.left_side()), "right": Dzen.render(&line.right_side())})
+}
+
+// Appends everything `inner` is asked to show to a recording. Every bar
+// has its own handle to the file, and writes whole lines at a time.
+pub struct Recorder {
+    inner: Box<dyn Backend>,
+    path: String,
+    bar: String,
+    file: Option<File>,
+}
+
+impl Recorder {
+    pub fn new(inner: Box<dyn Backend>, path: String, bar: String) -> Self {
+        Recorder {
+            inner,
+            path,
+            bar,
+            file: None,
+        }
+    }
+
+    fn write(&mut self, mut entry: Value) {
+        let file = match &mut self.file {
+            Some(f) => f,
+            None => return,
+        };
+        entry["t"] = Value::from(now());
+        entry["bar"] = Value::from(self.bar.as_str());
+        let mut s = entry.to_string();
+        s.push('\n');
+        if let Err(e) = file.write_all(s.as_bytes()) {
+            log::warn!("stopped recording to {} because '{}'", self.path, e);
+            self.file = None;
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for Recorder {
+    async fn spawn(&mut self) -> io::Result<()> {
+        match OpenOptions::new().create(true).append(true).open(&self.path) {
+            Ok(f) => self.file = Some(f),
+            Err(e) => log::warn!("couldn't record to {} because '{}'", self.path, e),
+        }
+        self.inner.spawn().await
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        self.write(json!({"line": line_to_json(line), "composed": composed(line)}));
+        self.inner.render(line).await
+    }
+
+    fn control(&mut self, msg: &str) {
+        self.write(json!({"msg": msg}));
+        self.inner.control(msg);
+    }
+
+    fn handle_clicks(&mut self, to_pipo: mpsc::Sender<String>) {
+        self.inner.handle_clicks(to_pipo);
+    }
+
+    fn supports_tray(&self) -> bool {
+        self.inner.supports_tray()
+    }
+
+    fn supports_pango(&self) -> bool {
+        self.inner.supports_pango()
+    }
+
+    fn fit(&mut self) -> Option<&Fit> {
+        self.inner.fit()
+    }
+
+    fn restart_tray(&mut self, delay: u64) {
+        self.inner.restart_tray(delay);
+    }
+
+    async fn teardown(&mut self) {
+        self.inner.teardown().await;
+    }
+}
+
+struct OwnedSegment {
+    name: String,
+    text: Vec<Markup>,
+    priority: i32,
+}
+
+fn segments_from_json(v: &Value) -> Option<Vec<OwnedSegment>> {
+    v.as_array()?
+        .iter()
+        .map(|s| Some(OwnedSegment {
+            name: s["name"].as_str()?.to_string(),
+            text: markup::parse(s["text"].as_str()?),
+            priority: s["priority"].as_i64().unwrap_or(0) as i32,
+        }))
+        .collect()
+}
+
+fn borrow(segs: &[OwnedSegment]) -> Vec<Segment<'_>> {
+    segs.iter()
+        .map(|s| Segment {name: &s.name, text: s.text.as_slice().into(), priority: s.priority})
+        .collect()
+}
+
+// composes the recorded line again, `None` if it is malformed
+fn recompose(entry: &Value) -> Option<Value> {
+    let line = &entry["line"];
+    let left = segments_from_json(&line["left"])?;
+    let right = segments_from_json(&line["right"])?;
+    let separator = markup::parse(line["separator"].as_str()?);
+    let line = Line {
+        left: borrow(&left),
+        right: borrow(&right),
+        separator: &separator,
+        padding: line["padding"].as_u64()? as usize,
+    };
+    Some(composed(&line))
+}
+
+// Composes every line of the recording at `path` again and prints the
+// recording as it would be now. Returns the exit code, which is 1 if
+// anything came out differently.
+pub fn replay(path: &str) -> i32 {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            log::error!("couldn't open recording {} because '{}'", path, e);
+            return 2;
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let out = stdout.lock();
+    replay_to(BufReader::new(file), out)
+}
+
+fn replay_to(recording: impl BufRead, mut out: impl Write) -> i32 {
+    let mut differ = 0;
+    for (i, line) in recording.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                log::error!("couldn't read recording '{}'", e);
+                return 2;
+            }
+        };
+        let mut entry: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("skipping line {} of the recording '{}'", i + 1, e);
+                continue;
+            }
+        };
+
+        if !entry["line"].is_null() {
+            let now = match recompose(&entry) {
+                Some(c) => c,
+                None => {
+                    log::warn!("skipping malformed line {} of the recording", i + 1);
+                    continue;
+                }
+            };
+            for side in ["left", "right"].iter() {
+                if entry["composed"][side] != now[side] {
+                    differ += 1;
+                    log::warn!("line {}: the {} side of {} was {} but is now {}",
+                               i + 1, side, entry["bar"], entry["composed"][side], now[side]);
+                }
+                // other backends get it through the markup tree
+                let dzen = now[side].as_str().unwrap_or("");
+                let back = Dzen.render(&markup::parse(dzen));
+                if back != dzen {
+                    log::warn!("line {}: the {} side of {} is {} as markup", i + 1, side, entry["bar"], back);
+                }
+            }
+            entry["composed"] = now;
+        }
+
+        if let Err(e) = writeln!(out, "{}", entry) {
+            log::error!("couldn't print replay '{}'", e);
+            return 2;
+        }
+    }
+
+    if differ > 0 {
+        log::warn!("{} sides came out differently", differ);
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDING: &str = include_str!("../../../fixtures/recording.jsonl");
+
+    fn replayed(recording: &str) -> (i32, String) {
+        let mut out = Vec::new();
+        let code = replay_to(recording.as_bytes(), &mut out);
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn fixture_replays_unchanged() {
+        let (code, out) = replayed(RECORDING);
+        assert_eq!(code, 0);
+        assert_eq!(out, RECORDING);
+    }
+
+    #[test]
+    fn changed_composition_is_reported() {
+        let changed = RECORDING.replacen("\"left\":\"", "\"left\":\"old ", 1);
+        let (code, out) = replayed(&changed);
+        assert_eq!(code, 1);
+        assert_eq!(out, RECORDING);
+    }
+}
@@ -0,0 +1,672 @@
+pub mod xpm;
+mod tray;
+pub mod tray_window;
+
+use async_trait::async_trait;
+use futures::future::poll_fn;
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+use mio::unix::EventedFd;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::task;
+use tokio;
+use tokio::io::{self, PollEvented};
+use tokio::select;
+use tokio::sync::{mpsc, Notify};
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use crate::config::*;
+use crate::bar::BarConfig;
+use crate::dzen_format::markup::{self, Markup, Step};
+use crate::tasks::stats::Stats;
+use super::{Backend,Line,run_click,theme_rgb};
+use super::fit::{CharWidths,Fit,Measure,Space,to_char2b};
+
+const HEIGHT: u16 = 26;
+
+type XResult<T> = Result<T, Box<dyn Error>>;
+
+fn to_io(e: Box<dyn Error>) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn intern(conn: &RustConnection, name: &str) -> XResult<Atom> {
+    Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+}
+
+// https://specifications.freedesktop.org/wm-spec/latest/
+// on top of every desktop, without decorations
+fn dock(conn: &RustConnection, win: Window) -> XResult<()> {
+    let cardinal = AtomEnum::CARDINAL;
+    let atom = AtomEnum::ATOM;
+    let replace = PropMode::REPLACE;
+    let wtype = intern(conn, "_NET_WM_WINDOW_TYPE")?;
+    let dock = intern(conn, "_NET_WM_WINDOW_TYPE_DOCK")?;
+    conn.change_property32(replace, win, wtype, atom, &[dock])?;
+
+    let state = intern(conn, "_NET_WM_STATE")?;
+    let sticky = intern(conn, "_NET_WM_STATE_STICKY")?;
+    let above = intern(conn, "_NET_WM_STATE_ABOVE")?;
+    conn.change_property32(replace, win, state, atom, &[sticky, above])?;
+    let desktop = intern(conn, "_NET_WM_DESKTOP")?;
+    conn.change_property32(replace, win, desktop, cardinal, &[0xffffffff])?;
+
+    conn.change_property8(replace, win, AtomEnum::WM_NAME, AtomEnum::STRING, b"statusbar")?;
+    conn.change_property8(replace, win, AtomEnum::WM_CLASS, AtomEnum::STRING, b"statusbar\0statusbar\0")?;
+    Ok(())
+}
+
+struct FontInfo {
+    font: Font,
+    ascent: i16,
+    descent: i16,
+    widths: CharWidths,
+}
+
+impl FontInfo {
+    fn open(conn: &RustConnection, name: &str) -> XResult<Self> {
+        let font = conn.generate_id()?;
+        conn.open_font(font, name.as_bytes())?.check()?;
+        let reply = conn.query_font(font)?.reply()?;
+        Ok(FontInfo {
+            font,
+            ascent: reply.font_ascent,
+            descent: reply.font_descent,
+            widths: CharWidths(reply),
+        })
+    }
+
+    fn text_width(&self, s: &[Char2b]) -> i32 {
+        self.widths.text_width(s)
+    }
+}
+
+enum Op {
+    Text {x: i32, text: Vec<Char2b>, fg: u32, bg: u32},
+    Rect {x: i32, y: i32, w: u16, h: u16, color: u32},
+    Icon {x: i32, icon: Rc<xpm::Image>, bg: u32},
+}
+
+// a `^ca` area, with `x0` and `x1` relative to the side it is on
+struct Area {
+    x0: i32,
+    x1: i32,
+    button: u8,
+    cmd: String,
+}
+
+// what `layout` needs from the X server
+trait Resources {
+    fn text_width(&self, s: &[Char2b]) -> i32;
+    fn color(&mut self, c: &str) -> u32;
+    fn icon(&mut self, path: &str) -> Option<Rc<xpm::Image>>;
+}
+
+// What to draw for `tree` starting at x = 0, and how wide it is. `fg`
+// and `bg` are the default colours.
+fn layout<R: Resources>(res: &mut R, tree: &[Markup], fg: u32, bg: u32) -> (Vec<Op>, Vec<Area>, i32) {
+    let (dfg, dbg) = (fg, bg);
+    let (mut fg, mut bg) = (fg, bg);
+    let mut ops = Vec::new();
+    let mut areas = Vec::new();
+    let mut open: Vec<(i32, u8, String)> = Vec::new();
+    let mut x = 0;
+
+    // like dzen, closing a colour goes back to the default one
+    markup::steps(tree, &mut |s| match s {
+        Step::Open(Markup::Color(c, _)) => fg = res.color(c),
+        Step::Open(Markup::Background(c, _)) => bg = res.color(c),
+        Step::Open(Markup::Click(b, cmd, _)) => open.push((x, *b as u8, cmd.trim().to_string())),
+        Step::Close(Markup::Color(..)) => fg = dfg,
+        Step::Close(Markup::Background(..)) => bg = dbg,
+        Step::Close(Markup::Click(..)) => {
+            if let Some((x0, button, cmd)) = open.pop() {
+                areas.push(Area {x0, x1: x, button, cmd});
+            }
+        },
+        Step::Leaf(Markup::Text(t)) => {
+            let text = to_char2b(t);
+            let w = res.text_width(&text);
+            ops.push(Op::Text {x, text, fg, bg});
+            x += w;
+        },
+        Step::Leaf(Markup::Tag(t, c)) if t == "fg" => fg = if c.is_empty() {dfg} else {res.color(c)},
+        Step::Leaf(Markup::Tag(t, c)) if t == "bg" => bg = if c.is_empty() {dbg} else {res.color(c)},
+        Step::Leaf(Markup::Shift(dx, _)) => x += *dx as i32,
+        Step::Leaf(Markup::Position(ax, _)) => x = *ax as i32,
+        Step::Leaf(Markup::Rect(w, h)) => {
+            let h = std::cmp::min(*h, HEIGHT as usize) as u16;
+            let w = std::cmp::min(*w, u16::MAX as usize) as u16;
+            let y = ((HEIGHT - h) / 2) as i32;
+            ops.push(Op::Rect {x, y, w, h, color: fg});
+            x += w as i32;
+        },
+        Step::Leaf(Markup::Icon(path)) => {
+            if let Some(icon) = res.icon(path) {
+                let w = icon.width as i32;
+                ops.push(Op::Icon {x, icon, bg});
+                x += w;
+            }
+        },
+        _ => (),
+    });
+    (ops, areas, x)
+}
+
+// the innermost area under `x` for `button`
+fn hit(areas: &[Area], x: i32, button: u8) -> Option<&Area> {
+    areas.iter().find(|a| a.button == button && a.x0 <= x && x < a.x1)
+}
+
+struct Inner {
+    conn: RustConnection,
+    root: Window,
+    win: Window,
+    pixmap: Pixmap,
+    gc: Gcontext,
+    depth: u8,
+    width: u16,
+    colormap: Colormap,
+    msb_first: bool,
+    font: FontInfo,
+    fg: u32,
+    bg: u32,
+    colors: HashMap<String, u32>,
+    icons: HashMap<String, Option<Rc<xpm::Image>>>,
+    // clickable areas in window coordinates, innermost first
+    areas: Vec<Area>,
+    // what was drawn last, to draw again when the tray changes
+    sides: (Vec<Markup>, Vec<Markup>),
+    tray: tray::Tray,
+    closed: bool,
+}
+
+impl Inner {
+    fn create(config: &BarConfig) -> XResult<Self> {
+        let (conn, screen_num) = RustConnection::connect(None)?;
+        let screen = &conn.setup().roots[screen_num];
+        let root = screen.root;
+        let depth = screen.root_depth;
+        let visual = screen.root_visual;
+        let colormap = screen.default_colormap;
+        let msb_first = conn.setup().image_byte_order == ImageOrder::MSB_FIRST;
+        let (x, y, width, _) = config.get_rect();
+
+        let font = FontInfo::open(&conn, X11_FONT)?;
+        let fg = theme_rgb("fg").unwrap_or(0xffffff);
+        let bg = theme_rgb("bg").unwrap_or(0);
+
+        let win = conn.generate_id()?;
+        conn.create_window(depth, win, root, x, y, width, HEIGHT, 0,
+                           WindowClass::INPUT_OUTPUT, visual,
+                           &CreateWindowAux::new()
+                           .background_pixel(bg)
+                           .event_mask(EventMask::EXPOSURE | EventMask::BUTTON_PRESS
+                                       // tray icons going away
+                                       | EventMask::SUBSTRUCTURE_NOTIFY
+                                       // timestamps for the tray
+                                       | EventMask::PROPERTY_CHANGE))?;
+
+        let pixmap = conn.generate_id()?;
+        conn.create_pixmap(depth, pixmap, win, width, HEIGHT)?;
+        let gc = conn.generate_id()?;
+        conn.create_gc(gc, win, &CreateGCAux::new()
+                       .foreground(fg)
+                       .background(bg)
+                       .font(font.font)
+                       .graphics_exposures(0))?;
+
+        dock(&conn, win)?;
+        let cardinal = AtomEnum::CARDINAL;
+        let replace = PropMode::REPLACE;
+        let top = (y as u32) + HEIGHT as u32;
+        let x0 = x as u32;
+        let x1 = x0 + width as u32 - 1;
+        let strut = intern(&conn, "_NET_WM_STRUT")?;
+        conn.change_property32(replace, win, strut, cardinal, &[0, 0, top, 0])?;
+        let strut_partial = intern(&conn, "_NET_WM_STRUT_PARTIAL")?;
+        conn.change_property32(replace, win, strut_partial, cardinal,
+                               &[0, 0, top, 0, 0, 0, 0, 0, x0, x1, 0, 0])?;
+
+
+        conn.map_window(win)?;
+        conn.flush()?;
+
+        let tray = tray::Tray::new(&conn, screen_num)?;
+
+        Ok(Inner {
+            conn,
+            root,
+            win,
+            pixmap,
+            gc,
+            depth,
+            width,
+            colormap,
+            msb_first,
+            font,
+            fg,
+            bg,
+            colors: HashMap::new(),
+            icons: HashMap::new(),
+            areas: Vec::new(),
+            sides: (Vec::new(), Vec::new()),
+            tray,
+            closed: false,
+        })
+    }
+
+    fn draw_op(&self, op: &Op, dx: i32) -> XResult<()> {
+        let conn = &self.conn;
+        match op {
+            Op::Text {x, text, fg, bg} => {
+                conn.change_gc(self.gc, &ChangeGCAux::new().foreground(*fg).background(*bg))?;
+                let baseline = (HEIGHT as i16 - self.font.ascent - self.font.descent) / 2 + self.font.ascent;
+                let mut x = x + dx;
+                // the length is sent as a byte
+                for chunk in text.chunks(255) {
+                    conn.image_text16(self.pixmap, self.gc, x as i16, baseline, chunk)?;
+                    x += self.font.text_width(chunk);
+                }
+            },
+            Op::Rect {x, y, w, h, color} => {
+                conn.change_gc(self.gc, &ChangeGCAux::new().foreground(*color))?;
+                let r = Rectangle {x: (x + dx) as i16, y: *y as i16, width: *w, height: *h};
+                conn.poly_fill_rectangle(self.pixmap, self.gc, &[r])?;
+            },
+            Op::Icon {x, icon, bg} => {
+                let h = std::cmp::min(icon.height, HEIGHT);
+                let mut data = Vec::with_capacity(icon.width as usize * h as usize * 4);
+                for p in icon.pixels.iter().take(icon.width as usize * h as usize) {
+                    let p = p.unwrap_or(*bg);
+                    if self.msb_first {
+                        data.extend_from_slice(&p.to_be_bytes());
+                    } else {
+                        data.extend_from_slice(&p.to_le_bytes());
+                    }
+                }
+                let y = ((HEIGHT - h) / 2) as i16;
+                conn.put_image(ImageFormat::Z_PIXMAP, self.pixmap, self.gc, icon.width, h,
+                               (x + dx) as i16, y, 0, self.depth, &data)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, left: &[Markup], right: &[Markup]) -> XResult<()> {
+        let (fg, bg) = (self.fg, self.bg);
+        let (lops, lareas, _) = layout(self, left, fg, bg);
+        let (rops, rareas, rwidth) = layout(self, right, fg, bg);
+        let rx = self.width as i32 - self.tray.width() as i32 - rwidth;
+        self.sides = (left.to_vec(), right.to_vec());
+
+        self.conn.change_gc(self.gc, &ChangeGCAux::new().foreground(self.bg))?;
+        let all = Rectangle {x: 0, y: 0, width: self.width, height: HEIGHT};
+        self.conn.poly_fill_rectangle(self.pixmap, self.gc, &[all])?;
+
+        for op in lops.iter() {
+            self.draw_op(op, 0)?;
+        }
+        for op in rops.iter() {
+            self.draw_op(op, rx)?;
+        }
+
+        self.areas = lareas.into_iter()
+            .chain(rareas.into_iter().map(|a| Area {x0: a.x0 + rx, x1: a.x1 + rx, ..a}))
+            .collect();
+        self.show()
+    }
+
+    // copies the finished picture to the window
+    fn show(&self) -> XResult<()> {
+        self.conn.copy_area(self.pixmap, self.win, self.gc, 0, 0, 0, 0, self.width, HEIGHT)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    // Moves the tray icons and the right side to make room for them,
+    // until the printer has shrunk the line for the new room.
+    fn tray_changed(&mut self) -> XResult<()> {
+        self.tray.place(&self.conn, self.width, HEIGHT)?;
+        let (left, right) = self.sides.clone();
+        self.draw(&left, &right)
+    }
+
+    fn click(&self, x: i32, button: u8) -> Option<String> {
+        hit(&self.areas, x, button).map(|a| a.cmd.clone())
+    }
+
+    fn close(&mut self) -> XResult<()> {
+        self.closed = true;
+        // the icons would be destroyed with the window
+        self.tray.close(&self.conn, self.root)?;
+        self.conn.destroy_window(self.win)?;
+        self.conn.free_pixmap(self.pixmap)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl Resources for Inner {
+    fn text_width(&self, s: &[Char2b]) -> i32 {
+        self.font.text_width(s)
+    }
+
+    fn color(&mut self, c: &str) -> u32 {
+        if let Some(rgb) = theme_rgb(c) {
+            return rgb;
+        }
+        let c = *THEME.color.get(c).unwrap_or(&c);
+        if let Some(p) = self.colors.get(c) {
+            return *p;
+        }
+
+        let pixel = self.conn.alloc_named_color(self.colormap, c.as_bytes())
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|r| Ok(r.reply()?.pixel))
+            .unwrap_or_else(|e| {
+                log::warn!("unknown colour '{}' '{}'", c, e);
+                self.fg
+            });
+        self.colors.insert(c.to_string(), pixel);
+        pixel
+    }
+
+    fn icon(&mut self, path: &str) -> Option<Rc<xpm::Image>> {
+        if let Some(i) = self.icons.get(path) {
+            return i.clone();
+        }
+        let icon = match std::fs::read_to_string(path) {
+            Ok(content) => xpm::parse(&content).map(Rc::new),
+            Err(e) => {
+                log::warn!("couldn't read icon {} because '{}'", path, e);
+                None
+            }
+        };
+        if icon.is_none() {
+            log::warn!("couldn't parse icon {}", path);
+        }
+        self.icons.insert(path.to_string(), icon.clone());
+        icon
+    }
+}
+
+// returns whether the tray icons changed
+fn tray_event(inner: &mut Inner, ev: &Event) -> XResult<bool> {
+    let changed = inner.tray.event(&inner.conn, inner.win, inner.root, inner.bg, ev)?;
+    if changed {
+        inner.tray_changed()?;
+    }
+    Ok(changed)
+}
+
+struct XFd(RawFd);
+
+impl Evented for XFd {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> std::io::Result<()> {
+        EventedFd(&self.0).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> std::io::Result<()> {
+        EventedFd(&self.0).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> std::io::Result<()> {
+        EventedFd(&self.0).deregister(poll)
+    }
+}
+
+async fn readable(fd: &PollEvented<XFd>) -> io::Result<()> {
+    poll_fn(|cx| match fd.poll_read_ready(cx, Ready::readable()) {
+        task::Poll::Ready(Ok(_)) => task::Poll::Ready(fd.clear_read_ready(cx, Ready::readable())),
+        task::Poll::Ready(Err(e)) => task::Poll::Ready(Err(e)),
+        task::Poll::Pending => task::Poll::Pending,
+    }).await
+}
+
+// Handles clicks and redraws. Events can be read from the connection
+// while drawing, so `wake` makes us look for them again. `relayout` is
+// notified when the tray changes.
+async fn events(inner: Rc<RefCell<Inner>>, wake: Rc<Notify>, relayout: Rc<Notify>, mut to_pipo: mpsc::Sender<String>) {
+    let fd = XFd(inner.borrow().conn.stream().as_raw_fd());
+    let fd = match PollEvented::new(fd) {
+        Ok(fd) => fd,
+        Err(e) => {
+            log::error!("couldn't wait on the X connection '{}'", e);
+            return;
+        }
+    };
+
+    loop {
+        loop {
+            let ev = inner.borrow().conn.poll_for_event();
+            let cmd = match ev {
+                Ok(Some(Event::Expose(e))) if e.count == 0 => {
+                    if let Err(e) = inner.borrow().show() {
+                        log::warn!("couldn't redraw '{}'", e);
+                    }
+                    continue;
+                },
+                Ok(Some(Event::ButtonPress(e))) => inner.borrow().click(e.event_x as i32, e.detail),
+                Ok(Some(ev)) => {
+                    match tray_event(&mut inner.borrow_mut(), &ev) {
+                        Ok(true) => relayout.notify(),
+                        Ok(false) => (),
+                        Err(e) => log::warn!("tray trouble '{}'", e),
+                    }
+                    continue;
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    if !inner.borrow().closed {
+                        log::error!("lost the X connection '{}'", e);
+                    }
+                    return;
+                }
+            };
+            if let Some(c) = cmd {
+                if run_click(&c, &mut to_pipo).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        if inner.borrow().closed {
+            break;
+        }
+
+        select! {
+            r = readable(&fd) => if let Err(e) = r {
+                log::error!("couldn't wait on the X connection '{}'", e);
+                break;
+            },
+            _ = wake.notified() => ()
+        }
+    }
+}
+
+// Our own dock window spanning the whole output, drawn with a core X
+// font. It is its own system tray, so the tray is on the bar's output.
+pub struct X11Backend {
+    config: BarConfig,
+    stats: Stats,
+    inner: Option<Rc<RefCell<Inner>>>,
+    wake: Rc<Notify>,
+    relayout: Rc<Notify>,
+    last: (Vec<Markup>, Vec<Markup>),
+    fit: Option<Fit>,
+    tray_started: bool,
+}
+
+impl X11Backend {
+    pub fn new(config: BarConfig, stats: Stats) -> Self {
+        X11Backend {
+            config,
+            stats,
+            inner: None,
+            wake: Rc::new(Notify::new()),
+            relayout: Rc::new(Notify::new()),
+            last: (Vec::new(), Vec::new()),
+            fit: None,
+            tray_started: false,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for X11Backend {
+    async fn spawn(&mut self) -> io::Result<()> {
+        let inner = Inner::create(&self.config).map_err(to_io)?;
+        let w = inner.width as i32;
+        let left = (w as f32 * self.config.get_split()) as i32;
+        self.fit = Some(Fit {
+            measure: Measure::new(inner.font.widths.clone()),
+            space: Space {left, right: w - left, shared: true},
+        });
+        self.inner = Some(Rc::new(RefCell::new(inner)));
+        Ok(())
+    }
+
+    async fn render(&mut self, line: &Line<'_>) -> io::Result<bool> {
+        let inner = match &self.inner {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+
+        let sides = (line.left_side(), line.right_side());
+        if sides == self.last {
+            return Ok(false);
+        }
+        inner.borrow_mut().draw(&sides.0, &sides.1).map_err(to_io)?;
+        self.wake.notify();
+        self.last = sides;
+        Ok(true)
+    }
+
+    fn handle_clicks(&mut self, to_pipo: mpsc::Sender<String>) {
+        if let Some(inner) = &self.inner {
+            tokio::task::spawn_local(events(inner.clone(), self.wake.clone(), self.relayout.clone(), to_pipo));
+        }
+    }
+
+    fn supports_tray(&self) -> bool {
+        true
+    }
+
+    fn layout_changed(&self) -> Option<Rc<Notify>> {
+        Some(self.relayout.clone())
+    }
+
+    // the right side gets whatever the tray doesn't need
+    fn fit(&mut self) -> Option<&Fit> {
+        let (w, tray) = match &self.inner {
+            Some(i) => {
+                let i = i.borrow();
+                (i.width as i32, i.tray.width() as i32)
+            },
+            None => return None,
+        };
+        let fit = self.fit.as_mut()?;
+        fit.space.right = w - fit.space.left - tray;
+        Some(fit)
+    }
+
+    // nothing to wait for, only to take the tray back if someone took it
+    fn restart_tray(&mut self, _delay: u64) {
+        let inner = match &self.inner {
+            Some(i) => i,
+            None => return,
+        };
+        let inner = inner.borrow();
+        if inner.tray.is_owner() {
+            return;
+        }
+        if self.tray_started {
+            self.stats.tray_restart(self.config.get_output());
+        }
+        self.tray_started = true;
+
+        if let Err(e) = inner.tray.request(&inner.conn, inner.win) {
+            log::warn!("couldn't be the system tray '{}'", e);
+        }
+    }
+
+    async fn teardown(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            if let Err(e) = inner.borrow_mut().close() {
+                log::warn!("couldn't close the bar window '{}'", e);
+            }
+            self.wake.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every character is 6 pixels wide, colours are their length
+    struct Fake;
+
+    impl Resources for Fake {
+        fn text_width(&self, s: &[Char2b]) -> i32 {
+            s.len() as i32 * 6
+        }
+
+        fn color(&mut self, c: &str) -> u32 {
+            c.len() as u32
+        }
+
+        fn icon(&mut self, _path: &str) -> Option<Rc<xpm::Image>> {
+            Some(Rc::new(xpm::Image {width: 10, height: 10, pixels: vec![None; 100]}))
+        }
+    }
+
+    fn layout_of(s: &str) -> (Vec<Op>, Vec<Area>, i32) {
+        layout(&mut Fake, &markup::parse(s), 1, 2)
+    }
+
+    #[test]
+    fn layout_places_everything_in_order() {
+        let (ops, _, width) = layout_of("ab^fg(red)c^fg()^i(x.xpm)^p(4)^r(3x40)d");
+        let xs: Vec<i32> = ops.iter().map(|op| match op {
+            Op::Text {x, ..} | Op::Rect {x, ..} | Op::Icon {x, ..} => *x,
+        }).collect();
+        assert_eq!(xs, vec![0, 12, 18, 28 + 4, 35]);
+        assert_eq!(width, 41);
+
+        match &ops[1] {
+            Op::Text {fg, bg, ..} => assert_eq!((*fg, *bg), (3, 2)),
+            _ => panic!("not text"),
+        }
+        match &ops[4] {
+            Op::Text {fg, ..} => assert_eq!(*fg, 1),
+            _ => panic!("not text"),
+        }
+        // at most as high as the bar
+        match &ops[3] {
+            Op::Rect {h, y, ..} => assert_eq!((*h, *y), (HEIGHT, 0)),
+            _ => panic!("not a rect"),
+        }
+    }
+
+    #[test]
+    fn clicks_hit_the_innermost_area() {
+        let (_, areas, _) = layout_of("a^ca(1,outer)bb^ca(1,inner)c^ca()d^ca()^ca(3,right)e^ca()");
+        let cmd = |x, b| hit(&areas, x, b).map(|a| a.cmd.as_str());
+        assert_eq!(cmd(0, 1), None);
+        assert_eq!(cmd(6, 1), Some("outer"));
+        assert_eq!(cmd(18, 1), Some("inner"));
+        assert_eq!(cmd(23, 1), Some("inner"));
+        assert_eq!(cmd(24, 1), Some("outer"));
+        assert_eq!(cmd(30, 1), None);
+        assert_eq!(cmd(30, 3), Some("right"));
+        assert_eq!(cmd(18, 3), None);
+    }
+}
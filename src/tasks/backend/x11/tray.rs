@@ -0,0 +1,196 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::NONE;
+use super::{XResult, intern};
+
+// https://specifications.freedesktop.org/systemtray-spec/latest/
+// https://specifications.freedesktop.org/xembed-spec/latest/
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_VERSION: u32 = 0;
+
+// every icon gets a square this big
+pub const ICON_SIZE: u16 = 22;
+
+fn client_message(window: Window, type_: Atom, data: [u32; 5]) -> ClientMessageEvent {
+    ClientMessageEvent {
+        response_type: CLIENT_MESSAGE_EVENT,
+        format: 32,
+        sequence: 0,
+        window,
+        type_,
+        data: data.into(),
+    }
+}
+
+// The system tray of the screen, with the icons embedded at the right
+// edge of a window. The window has to select `PROPERTY_CHANGE` and
+// `SUBSTRUCTURE_NOTIFY`, and give its events to `event`.
+pub struct Tray {
+    selection: Atom,
+    opcode: Atom,
+    manager: Atom,
+    xembed: Atom,
+    orientation: Atom,
+    // changed to get a server timestamp
+    stamp: Atom,
+    // when we became the tray, if we are
+    owner: Option<Timestamp>,
+    icons: Vec<Window>,
+}
+
+impl Tray {
+    pub fn new(conn: &RustConnection, screen_num: usize) -> XResult<Self> {
+        Ok(Tray {
+            selection: intern(conn, &format!("_NET_SYSTEM_TRAY_S{}", screen_num))?,
+            opcode: intern(conn, "_NET_SYSTEM_TRAY_OPCODE")?,
+            manager: intern(conn, "MANAGER")?,
+            xembed: intern(conn, "_XEMBED")?,
+            orientation: intern(conn, "_NET_SYSTEM_TRAY_ORIENTATION")?,
+            stamp: intern(conn, "_STATUSBAR_TIMESTAMP")?,
+            owner: None,
+            icons: Vec::new(),
+        })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.icons.len() as u16 * ICON_SIZE
+    }
+
+    // Starts becoming the tray of the screen. ICCCM wants a real
+    // timestamp for taking the selection, and the PropertyNotify of an
+    // empty change to `win` has one.
+    pub fn request(&self, conn: &RustConnection, win: Window) -> XResult<()> {
+        if self.owner.is_some() {
+            return Ok(());
+        }
+        conn.change_property8(PropMode::APPEND, win, self.stamp, AtomEnum::STRING, &[])?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    // Becomes the tray, taking over from whoever was it. Icons dock
+    // again when they see the `MANAGER` message.
+    fn acquire(&mut self, conn: &RustConnection, win: Window, root: Window, time: Timestamp) -> XResult<()> {
+        if self.owner.is_some() {
+            return Ok(());
+        }
+        let old = conn.get_selection_owner(self.selection)?.reply()?.owner;
+        if old != NONE {
+            log::info!("taking over the system tray from window {}", old);
+        }
+
+        // horizontal
+        conn.change_property32(PropMode::REPLACE, win, self.orientation, AtomEnum::CARDINAL, &[0])?;
+        conn.set_selection_owner(win, self.selection, time)?;
+        if conn.get_selection_owner(self.selection)?.reply()?.owner != win {
+            return Err("couldn't become the system tray".into());
+        }
+        self.owner = Some(time);
+
+        let ev = client_message(root, self.manager, [time, self.selection, win, 0, 0]);
+        conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, ev)?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    // Handles the events of `win` that concern the tray, returns
+    // whether the icons changed.
+    pub fn event(&mut self, conn: &RustConnection, win: Window, root: Window, bg: u32, ev: &Event) -> XResult<bool> {
+        match ev {
+            Event::PropertyNotify(e) if e.window == win && e.atom == self.stamp => {
+                if let Err(e) = self.acquire(conn, win, root, e.time) {
+                    log::warn!("couldn't be the system tray '{}'", e);
+                }
+                Ok(false)
+            },
+            Event::ClientMessage(e) => self.client_message(conn, win, bg, e),
+            Event::DestroyNotify(e) => Ok(self.remove(e.window)),
+            Event::ReparentNotify(e) if e.parent != win => Ok(self.remove(e.window)),
+            Event::SelectionClear(e) if e.selection == self.selection => {
+                log::warn!("some other program took over the system tray");
+                self.lost(conn, root)?;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // returns whether an icon docked
+    fn client_message(&mut self, conn: &RustConnection, win: Window, bg: u32, e: &ClientMessageEvent) -> XResult<bool> {
+        if self.owner.is_none() || e.type_ != self.opcode || e.format != 32 {
+            return Ok(false);
+        }
+        // balloon messages aren't shown
+        let data = e.data.as_data32();
+        let (time, icon) = (data[0], data[2]);
+        if data[1] != SYSTEM_TRAY_REQUEST_DOCK || self.icons.contains(&icon) {
+            return Ok(false);
+        }
+
+        // the icon survives us dying
+        conn.change_save_set(SetMode::INSERT, icon)?;
+        conn.change_window_attributes(icon, &ChangeWindowAttributesAux::new().background_pixel(bg))?;
+        conn.reparent_window(icon, win, 0, 0)?;
+        let ev = client_message(icon, self.xembed, [time, XEMBED_EMBEDDED_NOTIFY, 0, win, XEMBED_VERSION]);
+        conn.send_event(false, icon, EventMask::NO_EVENT, ev)?;
+        conn.map_window(icon)?;
+        self.icons.push(icon);
+        Ok(true)
+    }
+
+    // forgets an icon that was destroyed or taken elsewhere, returns
+    // whether it was ours
+    fn remove(&mut self, icon: Window) -> bool {
+        let len = self.icons.len();
+        self.icons.retain(|i| *i != icon);
+        len != self.icons.len()
+    }
+
+    // puts the icons at the right edge of a bar `width` wide
+    pub fn place(&self, conn: &RustConnection, width: u16, height: u16) -> XResult<()> {
+        let y = (height.saturating_sub(ICON_SIZE) / 2) as i32;
+        for (i, icon) in self.icons.iter().enumerate() {
+            let x = width as i32 - ((self.icons.len() - i) as i32 * ICON_SIZE as i32);
+            conn.configure_window(*icon, &ConfigureWindowAux::new()
+                                  .x(x)
+                                  .y(y)
+                                  .width(ICON_SIZE as u32)
+                                  .height(ICON_SIZE as u32))?;
+        }
+        conn.flush()?;
+        Ok(())
+    }
+
+    // someone else is the tray now
+    fn lost(&mut self, conn: &RustConnection, root: Window) -> XResult<()> {
+        self.owner = None;
+        self.release(conn, root)
+    }
+
+    pub fn is_owner(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    // gives the icons back to the root window, so that they can dock
+    // somewhere else
+    fn release(&mut self, conn: &RustConnection, root: Window) -> XResult<()> {
+        for icon in self.icons.drain(..) {
+            conn.unmap_window(icon)?;
+            conn.reparent_window(icon, root, 0, 0)?;
+            conn.change_save_set(SetMode::DELETE, icon)?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&mut self, conn: &RustConnection, root: Window) -> XResult<()> {
+        self.release(conn, root)?;
+        if let Some(time) = self.owner.take() {
+            conn.set_selection_owner(NONE, self.selection, time)?;
+        }
+        Ok(())
+    }
+}
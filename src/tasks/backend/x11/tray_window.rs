@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::os::unix::io::AsRawFd;
+use std::rc::Rc;
+use tokio;
+use tokio::io::PollEvented;
+use tokio::select;
+use tokio::sync::Notify;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use crate::bar::BarConfig;
+use crate::dzen_format::markup::Markup;
+use crate::tasks::stats::Stats;
+use super::super::theme_rgb;
+use super::{HEIGHT, XFd, XResult, dock, readable, tray};
+
+struct Inner {
+    conn: RustConnection,
+    root: Window,
+    win: Window,
+    bg: u32,
+    // right edge of the output, where the icons end
+    right: i16,
+    mapped: bool,
+    tray: tray::Tray,
+    closed: bool,
+}
+
+impl Inner {
+    fn create(config: &BarConfig) -> XResult<Self> {
+        let (conn, screen_num) = RustConnection::connect(None)?;
+        let screen = &conn.setup().roots[screen_num];
+        let root = screen.root;
+        let (x, y, width, _) = config.get_rect();
+        let right = x + width as i16;
+        let bg = theme_rgb("bg").unwrap_or(0);
+
+        // unmapped, and only as wide as the icons once there are any
+        let win = conn.generate_id()?;
+        conn.create_window(screen.root_depth, win, root, right - 1, y, 1, HEIGHT, 0,
+                           WindowClass::INPUT_OUTPUT, screen.root_visual,
+                           &CreateWindowAux::new()
+                           .background_pixel(bg)
+                           .event_mask(EventMask::SUBSTRUCTURE_NOTIFY
+                                       | EventMask::PROPERTY_CHANGE))?;
+        dock(&conn, win)?;
+        conn.flush()?;
+
+        let tray = tray::Tray::new(&conn, screen_num)?;
+
+        Ok(Inner {
+            conn,
+            root,
+            win,
+            bg,
+            right,
+            mapped: false,
+            tray,
+            closed: false,
+        })
+    }
+
+    // fits the window around the icons, at the right edge of the output
+    fn resize(&mut self) -> XResult<()> {
+        let w = self.tray.width();
+        if w == 0 {
+            if self.mapped {
+                self.conn.unmap_window(self.win)?;
+                self.mapped = false;
+            }
+            self.conn.flush()?;
+            return Ok(());
+        }
+
+        self.conn.configure_window(self.win, &ConfigureWindowAux::new()
+                                   .x((self.right - w as i16) as i32)
+                                   .width(w as u32))?;
+        if !self.mapped {
+            self.conn.map_window(self.win)?;
+            self.mapped = true;
+        }
+        self.tray.place(&self.conn, w, HEIGHT)
+    }
+
+    fn close(&mut self) -> XResult<()> {
+        self.closed = true;
+        self.tray.close(&self.conn, self.root)?;
+        self.conn.destroy_window(self.win)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+// Handles the tray's events until closed. `wake` makes us look at
+// `closed` again.
+async fn events(inner: Rc<RefCell<Inner>>, wake: Rc<Notify>, relayout: Rc<Notify>) {
+    let fd = XFd(inner.borrow().conn.stream().as_raw_fd());
+    let fd = match PollEvented::new(fd) {
+        Ok(fd) => fd,
+        Err(e) => {
+            log::error!("couldn't wait on the X connection of the tray '{}'", e);
+            return;
+        }
+    };
+
+    loop {
+        loop {
+            let ev = inner.borrow().conn.poll_for_event();
+            match ev {
+                Ok(Some(ev)) => {
+                    let mut i = inner.borrow_mut();
+                    let i = &mut *i;
+                    match i.tray.event(&i.conn, i.win, i.root, i.bg, &ev) {
+                        Ok(true) => match i.resize() {
+                            Ok(()) => relayout.notify(),
+                            Err(e) => log::warn!("couldn't resize the tray '{}'", e),
+                        },
+                        Ok(false) => (),
+                        Err(e) => log::warn!("tray trouble '{}'", e),
+                    }
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    if !inner.borrow().closed {
+                        log::error!("lost the X connection of the tray '{}'", e);
+                    }
+                    return;
+                }
+            }
+        }
+
+        if inner.borrow().closed {
+            break;
+        }
+
+        select! {
+            r = readable(&fd) => if let Err(e) = r {
+                log::error!("couldn't wait on the X connection of the tray '{}'", e);
+                break;
+            },
+            _ = wake.notified() => ()
+        }
+    }
+}
+
+// leaves `width` pixels of room for the icons after the right side
+pub fn pad(mut right: Vec<Markup>, width: u16) -> Vec<Markup> {
+    if width > 0 {
+        right.push(Markup::Shift(width as isize, None));
+    }
+    right
+}
+
+// A system tray in a dock window of its own, at the top right of the
+// bar's output, for backends that can't hold the icons themselves. It
+// is only as wide as its icons, which the bar has to leave room for.
+pub struct TrayWindow {
+    config: BarConfig,
+    stats: Stats,
+    inner: Option<Rc<RefCell<Inner>>>,
+    wake: Rc<Notify>,
+    relayout: Rc<Notify>,
+    started: bool,
+}
+
+impl TrayWindow {
+    pub fn new(config: BarConfig, stats: Stats) -> Self {
+        TrayWindow {
+            config,
+            stats,
+            inner: None,
+            wake: Rc::new(Notify::new()),
+            relayout: Rc::new(Notify::new()),
+            started: false,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.inner.as_ref().map_or(0, |i| i.borrow().tray.width())
+    }
+
+    pub fn pad(&self, right: Vec<Markup>) -> Vec<Markup> {
+        pad(right, self.width())
+    }
+
+    // notified when the width changes
+    pub fn layout_changed(&self) -> Rc<Notify> {
+        self.relayout.clone()
+    }
+
+    // creates the window the first time, and takes the tray back if
+    // someone took it
+    pub fn restart(&mut self) {
+        if self.inner.is_none() {
+            match Inner::create(&self.config) {
+                Ok(i) => {
+                    let i = Rc::new(RefCell::new(i));
+                    tokio::task::spawn_local(events(i.clone(), self.wake.clone(), self.relayout.clone()));
+                    self.inner = Some(i);
+                },
+                Err(e) => {
+                    log::warn!("couldn't create the tray window '{}'", e);
+                    return;
+                }
+            }
+        }
+
+        let inner = self.inner.as_ref().unwrap().borrow();
+        if inner.tray.is_owner() {
+            return;
+        }
+        if self.started {
+            self.stats.tray_restart(self.config.get_output());
+        }
+        self.started = true;
+
+        if let Err(e) = inner.tray.request(&inner.conn, inner.win) {
+            log::warn!("couldn't be the system tray '{}'", e);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            if let Err(e) = inner.borrow_mut().close() {
+                log::warn!("couldn't close the tray window '{}'", e);
+            }
+            self.wake.notify();
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use super::super::parse_rgb;
+
+// icons are about as high as the bar
+const MAX_SIDE: usize = 1024;
+// characters per pixel, XPM files use a few at most
+const MAX_CPP: usize = 8;
+
+// an icon as rows of 0xRRGGBB pixels, `None` is transparent
+pub struct Image {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<Option<u32>>,
+}
+
+// the strings of an XPM file are the only interesting part
+fn strings(content: &str) -> Vec<&str> {
+    let mut v = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find('"') {
+        rest = &rest[start+1..];
+        match rest.find('"') {
+            Some(end) => {
+                v.push(&rest[..end]);
+                rest = &rest[end+1..];
+            },
+            None => break,
+        }
+    }
+    v
+}
+
+// https://en.wikipedia.org/wiki/X_PixMap
+pub fn parse(content: &str) -> Option<Image> {
+    let strs = strings(content);
+    let mut header = strs.first()?.split_whitespace().map(|s| s.parse::<usize>().ok());
+    let width = header.next()??;
+    let height = header.next()??;
+    let ncolors = header.next()??;
+    let cpp = header.next()??;
+    // anything bigger is not an icon, and the sizes come from the file
+    if width == 0 || width > MAX_SIDE || height == 0 || height > MAX_SIDE
+        || cpp == 0 || cpp > MAX_CPP || ncolors > strs.len()
+    {
+        return None;
+    }
+    let rows = 1usize.checked_add(ncolors)?;
+
+    let mut colors = HashMap::new();
+    for line in strs.get(1..rows)? {
+        let key = line.get(..cpp)?;
+        let words: Vec<&str> = line[cpp..].split_whitespace().collect();
+        // the colour visual, like `c #ff0000`, and the value can
+        // contain spaces
+        let c = words.iter().position(|w| *w == "c")?;
+        let value = words[c+1..].iter()
+            .take_while(|w| !["m", "g", "g4", "s"].contains(w))
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let color = if value.eq_ignore_ascii_case("none") {
+            None
+        } else {
+            Some(parse_rgb(&value).unwrap_or(0))
+        };
+        colors.insert(key, color);
+    }
+
+    let mut pixels = Vec::with_capacity(width.checked_mul(height)?);
+    for row in strs.get(rows..rows.checked_add(height)?)? {
+        for x in 0..width {
+            let key = row.get(x.checked_mul(cpp)?..(x+1).checked_mul(cpp)?)?;
+            pixels.push(*colors.get(key)?);
+        }
+    }
+
+    Some(Image {
+        width: width as u16,
+        height: height as u16,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICON: &str = r##"/* XPM */
+static char *icon[] = {
+"3 2 2 1",
+"  c None",
+"# c #ff0000",
+"# #",
+" # "
+};"##;
+
+    #[test]
+    fn parses_pixels() {
+        let i = parse(ICON).unwrap();
+        assert_eq!((i.width, i.height), (3, 2));
+        let red = Some(0xff0000);
+        assert_eq!(i.pixels, vec![red, None, red, None, red, None]);
+    }
+
+    #[test]
+    fn rejects_huge_and_broken_headers() {
+        for header in ["99999999 99999999 2 1", "18446744073709551615 2 2 1", "3 2 18446744073709551615 1",
+                       "3 2 2 0", "3 2 2 18446744073709551615", "3 2 2"].iter() {
+            let xpm = ICON.replace("3 2 2 1", header);
+            assert!(parse(&xpm).is_none(), "{}", header);
+        }
+    }
+}
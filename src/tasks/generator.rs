@@ -19,25 +19,39 @@ pub mod tempgen;
 pub mod ipgen;
 pub mod onegen;
 pub mod batgen;
+pub mod lightgen;
+pub mod statsgen;
 
 use dbus_tokio::connection::IOResource;
 use tokio;
-use tokio::sync::mpsc;
-use tokio::sync::broadcast;
-use tokio::time::delay_for;
+use tokio::sync::{mpsc,watch};
+use tokio::time::{delay_for,timeout};
 use futures::stream::StreamExt;
 use tokio::select;
 use core::time::Duration;
+use std::time::Instant;
 use async_trait::async_trait;
-pub use super::{ExitReason,Msg};
+pub use super::ExitReason;
 use dbus::nonblock as DN;
 use std::sync::Arc;
+use std::rc::Rc;
 use futures::stream::{select_all};
 use either::{Left,Right};
+use inotify::{Inotify,WatchMask};
+use std::path::PathBuf;
 use crate::dzen_format::DzenBuilder;
+use crate::dzen_format::markup::{self, Markup};
+use super::sampler::{Sampler,Sample};
+use super::state::StateStore;
+use super::stats::Stats;
+use super::metrics::Metrics;
+use super::ready::ReadyToken;
 
 pub type Result<X> = std::result::Result<X, ExitReason>;
 
+// seconds an update may take before it is cancelled
+const DEFAULT_DEADLINE: u64 = 10;
+
 #[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
 #[allow(dead_code)]
 pub enum GenType {
@@ -51,6 +65,8 @@ pub enum GenType {
     IP,
     ONE,
     BAT,
+    LIGHT,
+    STATS,
 }
 
 #[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
@@ -64,6 +80,9 @@ pub struct GenArg {
     pub timeout: Option<u64>,
     pub arg: Option<String>,
     pub prepend: Option<DzenBuilder<'static>>,
+    pub step: Option<u64>,
+    pub restart_on_resume: bool,
+    pub deadline: Option<u64>,
 }
 
 impl GenId {
@@ -94,56 +113,169 @@ impl GenArg {
             timeout: None,
             arg: None,
             prepend: None,
+            step: None,
+            restart_on_resume: false,
+            deadline: None,
+        }
+    }
+
+    pub fn get_deadline(&self) -> Duration {
+        Duration::from_secs(self.deadline.unwrap_or(DEFAULT_DEADLINE))
+    }
+}
+
+// handles to the services generators can use
+#[derive(Clone)]
+pub struct Shared {
+    pub sampler: Sampler,
+    pub state: StateStore,
+    pub stats: Stats,
+    pub metrics: Metrics,
+}
+
+// The output of one generator. The receiving end always holds the newest
+// output, or `None` if nothing has been sent yet, so a busy generator
+// can't push out the output of others.
+pub struct Output {
+    send: watch::Sender<Option<Vec<Markup>>>,
+    last: Option<Vec<Markup>>,
+    ready: ReadyToken,
+}
+
+pub type Latest = watch::Receiver<Option<Vec<Markup>>>;
+
+impl Output {
+    pub fn channel(ready: ReadyToken) -> (Self, Latest) {
+        let (send, recv) = watch::channel(None);
+        (Output {send, last: None, ready}, recv)
+    }
+
+    // The generator has started, which happens by itself on the first
+    // output. Only needed by those that might not output anything.
+    pub fn ready(&mut self) {
+        self.ready.ready();
+    }
+
+    // Sends `s` unless it is the same as last time. Fails if there are
+    // no printers left.
+    pub fn send(&mut self, s: Vec<Markup>) -> Result<()> {
+        if self.last.as_ref() == Some(&s) {
+            return Ok(());
+        }
+        if self.send.broadcast(Some(s.clone())).is_err() {
+            return Err(ExitReason::failed("no printer is listening"));
+        }
+        self.last = Some(s);
+        self.ready.ready();
+        Ok(())
+    }
+
+    // Shows the last output in the stale color until something new is
+    // sent.
+    pub fn mark_stale(&mut self) -> Result<()> {
+        self.ready.ready();
+        let last = match self.last.take() {
+            Some(l) => l,
+            None => return Ok(()),
+        };
+        let stale_color = crate::config::THEME.color.get("stale").unwrap_or(&"#808080");
+        let mut last = last;
+        markup::walk_mut(&mut last, &mut |m| match m {
+            Markup::Color(c, _) => *c = stale_color.to_string(),
+            Markup::Tag(t, c) if t == "fg" => *c = stale_color.to_string(),
+            _ => (),
+        });
+        let s = DzenBuilder::new()
+            .append(last)
+            .colorize(*stale_color)
+            .into_markup();
+        if self.send.broadcast(Some(s)).is_err() {
+            return Err(ExitReason::failed("no printer is listening"));
         }
+        Ok(())
     }
 }
 
-struct TimerWrap<T>(T);
-struct DBusWrap<T>(T);
+struct TimerWrap<T>(T, Shared);
+struct DBusWrap<T>(T, Shared);
+struct FileWrap<T>(T, Shared);
+struct SampleWrap<T>(T, Shared);
 
-#[async_trait]
+#[async_trait(?Send)]
 pub trait Generator {
-    async fn start(&mut self, to_printer: broadcast::Sender<Msg>,
+    async fn start(&mut self, output: Output,
                    from_pipo: mpsc::Receiver<String>,
-                   id: GenId,
                    arg: GenArg,
                    name: String) -> ExitReason;
+    // kernel subsystems to receive `uevent <subsystem> <action>` messages from
+    fn uevent_subsystems(&self) -> Vec<&'static str> {vec!()}
+    // whether to receive `resume` after waking up from sleep
+    fn wants_resume(&self, _arg: &GenArg) -> bool {false}
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 pub trait TimerGenerator {
     async fn init(&mut self, _arg: &GenArg) -> Result<()> {Ok(())}
     async fn update(&mut self) -> Result<()>;
-    fn display(&self, name: &str, arg: &GenArg) -> Result<String>;
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>>;
     async fn finalize(&mut self) -> Result<()> {Ok(())}
     async fn on_msg(&mut self, _msg: String) -> Result<bool> {Ok(false)}
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(5)
     }
+    fn uevent_subsystems(&self) -> Vec<&'static str> {vec!()}
+    // called after waking up from sleep, right before an update, if
+    // `wants_resume`
+    fn on_resume(&mut self) {}
+    fn wants_resume(&self) -> bool {false}
+    // state to keep across restarts, saved after every message
+    fn save_state(&self) -> Option<String> {None}
+    fn restore_state(&mut self, _state: &str) {}
 }
 
-#[async_trait]
-impl<G: TimerGenerator + Sync + Send> Generator for TimerWrap<G> {
+#[async_trait(?Send)]
+impl<G: TimerGenerator> Generator for TimerWrap<G> {
     async fn start(&mut self,
-                   to_printer: broadcast::Sender<Msg>,
+                   mut output: Output,
                    mut from_pipo: mpsc::Receiver<String>,
-                   id: GenId,
                    arg: GenArg,
                    name: String) -> ExitReason
     {
         unwrap_er!(self.0.init(&arg).await);
+        if let Some(st) = self.1.state.get(&name) {
+            self.0.restore_state(&st);
+        }
         let mut run_update = true;
+        let mut stale = false;
         let mut delayer = delay_for(Duration::from_secs(0));
         let reason = loop {
             if run_update {
-                unwrap_er!(self.0.update().await);
+                // a hung update is cancelled and retried at the next tick
+                let started = Instant::now();
+                let res = timeout(arg.get_deadline(), self.0.update()).await;
+                self.1.stats.update(&name, started.elapsed());
+                match res {
+                    Ok(r) => {
+                        unwrap_er!(r);
+                        stale = false;
+                    },
+                    Err(_) => {
+                        log::warn!("'{}' didn't update in time, retrying later", name);
+                        if let Err(e) = output.mark_stale() {
+                            break e;
+                        }
+                        stale = true;
+                    }
+                }
                 run_update = false;
                 let delay = Duration::from_secs(self.0.get_delay(&arg));
                 delayer.reset(tokio::time::Instant::now() + delay);
             }
-            let s = unwrap_er!(self.0.display(&name, &arg));
-            if let Err(_) = to_printer.send(Msg::Gen(id, s)) {
-                break ExitReason::Error;
+            if !stale {
+                let s = unwrap_er!(self.0.display(&name, &arg));
+                if let Err(e) = output.send(s) {
+                    break e;
+                }
             }
             let msg = select! {
                 _ = &mut delayer => {
@@ -155,34 +287,52 @@ impl<G: TimerGenerator + Sync + Send> Generator for TimerWrap<G> {
                     Some(m) => Some(m)
                 }
             };
-            if let Some(m) = msg {
-                run_update = unwrap_er!(self.0.on_msg(m).await);
+            match msg {
+                // things have probably changed a lot while sleeping
+                Some(m) if m == "resume" => {
+                    self.0.on_resume();
+                    run_update = true;
+                },
+                Some(m) => {
+                    run_update = unwrap_er!(self.0.on_msg(m).await);
+                    self.1.state.set(&name, self.0.save_state());
+                },
+                None => (),
             }
         };
         unwrap_er!(self.0.finalize().await);
         reason
     }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        self.0.uevent_subsystems()
+    }
+
+    fn wants_resume(&self, _arg: &GenArg) -> bool {
+        self.0.wants_resume()
+    }
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 pub trait DBusGenerator {
     fn get_connection(&self) -> Result<(IOResource<DN::SyncConnection>, Arc<DN::SyncConnection>)>;
     async fn init(&mut self, _arg: &GenArg, _conn: Arc<DN::SyncConnection>) -> Result<()> {Ok(())}
-    async fn update(&mut self, conn: Arc<DN::SyncConnection>, name: &str, arg: &GenArg) -> Result<String>;
+    async fn update(&mut self, conn: Arc<DN::SyncConnection>, name: &str, arg: &GenArg) -> Result<Vec<Markup>>;
     fn interesting_signals(&self) -> Vec<dbus::message::MatchRule<'static>> {vec!()}
     async fn handle_signal(&mut self, _sig: usize, _data: dbus::message::Message) -> Result<()> {Ok(())}
     async fn handle_msg(&mut self, _msg: String) -> Result<()> {Ok(())}
+    fn save_state(&self) -> Option<String> {None}
+    fn restore_state(&mut self, _state: &str) {}
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 impl<G> Generator for DBusWrap<G>
-where G: DBusGenerator + Sync + Send
+where G: DBusGenerator
 {
     async fn start(
         &mut self,
-        to_printer: broadcast::Sender<Msg>,
+        mut output: Output,
         mut from_pipo: mpsc::Receiver<String>,
-        id: GenId,
         arg: GenArg,
         name: String
     ) -> ExitReason
@@ -192,6 +342,9 @@ where G: DBusGenerator + Sync + Send
         // declare main loop
         let main_loop = async {
             self.0.init(&arg, conn.clone()).await?;
+            if let Some(st) = self.1.state.get(&name) {
+                self.0.restore_state(&st);
+            }
 
             let int_sig = self.0.interesting_signals();
             let mut sigs_streams = Vec::new();
@@ -203,14 +356,31 @@ where G: DBusGenerator + Sync + Send
             }
 
             let mut stream = select_all(sigs_streams);
+            let mut retry = delay_for(Duration::from_secs(0));
 
             let res = loop {
-                let s = self.0.update(conn.clone(), name.as_str(), &arg).await?;
-                if let Err(_) = to_printer.send(Msg::Gen(id, s)) {
-                    break Err(ExitReason::Error);
-                }
+                let started = Instant::now();
+                let res = timeout(arg.get_deadline(), self.0.update(conn.clone(), name.as_str(), &arg)).await;
+                self.1.stats.update(&name, started.elapsed());
+                let stale = match res {
+                    Ok(s) => {
+                        if let Err(e) = output.send(s?) {
+                            break Err(e);
+                        }
+                        false
+                    },
+                    Err(_) => {
+                        log::warn!("'{}' didn't update in time, retrying later", name);
+                        if let Err(e) = output.mark_stale() {
+                            break Err(e);
+                        }
+                        retry.reset(tokio::time::Instant::now() + arg.get_deadline());
+                        true
+                    }
+                };
 
                 let msg = select! {
+                    _ = &mut retry, if stale => continue,
                     msg = from_pipo.recv() => match msg {
                         None => break Ok(()),
                         Some(s) => Right(s),
@@ -224,11 +394,9 @@ where G: DBusGenerator + Sync + Send
                     }
                     Right(s) => {
                         self.0.handle_msg(s).await?;
+                        self.1.state.set(&name, self.0.save_state());
                     }
-                    Left(None) => {
-                        log::error!("can't wait on more DBus signals?");
-                        break Err(ExitReason::Error);
-                    }
+                    Left(None) => break Err(ExitReason::failed("can't wait on more DBus signals")),
                 }
             };
 
@@ -241,10 +409,7 @@ where G: DBusGenerator + Sync + Send
 
         // wait for main loop or dbus disconnect
         let ret = tokio::select! {
-            err = &mut resource => {
-                log::error!("dbus connection lost. '{}'", err);
-                Err(ExitReason::Error)
-            },
+            err = &mut resource => Err(ExitReason::error("dbus connection lost", err)),
             ret = main_loop => ret
         };
 
@@ -255,17 +420,357 @@ where G: DBusGenerator + Sync + Send
     }
 }
 
-pub fn genid_to_generator(id: GenId) -> Box<dyn Generator + Send> {
+#[async_trait(?Send)]
+pub trait FileGenerator {
+    async fn init(&mut self, _arg: &GenArg) -> Result<()> {Ok(())}
+    fn watched_paths(&self) -> Vec<PathBuf>;
+    async fn update(&mut self) -> Result<()>;
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>>;
+    async fn finalize(&mut self) -> Result<()> {Ok(())}
+    async fn on_msg(&mut self, _msg: String) -> Result<bool> {Ok(false)}
+    // files in sysfs rarely emit any events, so they need to be polled
+    // every once in a while anyway
+    fn get_poll(&self, arg: &GenArg) -> Option<u64> {
+        arg.timeout
+    }
+    fn uevent_subsystems(&self) -> Vec<&'static str> {vec!()}
+    // called after waking up from sleep, right before an update, if
+    // `wants_resume`
+    fn on_resume(&mut self) {}
+    fn wants_resume(&self) -> bool {false}
+    fn save_state(&self) -> Option<String> {None}
+    fn restore_state(&mut self, _state: &str) {}
+    // called after every successful update
+    fn export_metrics(&mut self, _metrics: &Metrics) {}
+}
+
+#[async_trait(?Send)]
+impl<G: FileGenerator> Generator for FileWrap<G> {
+    async fn start(&mut self,
+                   mut output: Output,
+                   mut from_pipo: mpsc::Receiver<String>,
+                   arg: GenArg,
+                   name: String) -> ExitReason
+    {
+        unwrap_er!(self.0.init(&arg).await);
+        if let Some(st) = self.1.state.get(&name) {
+            self.0.restore_state(&st);
+        }
+
+        let mut inotify = unwrap_er!(Inotify::init().map_err(|e| ExitReason::error("couldn't start inotify", e)));
+        let mask = WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::ATTRIB
+            | WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM;
+        for p in self.0.watched_paths() {
+            if let Err(e) = inotify.add_watch(&p, mask) {
+                log::warn!("couldn't watch {:?} because '{}', relying on polling", p, e);
+            }
+        }
+        let mut events = unwrap_er!(inotify.event_stream([0; 1024]).map_err(|e| ExitReason::error("couldn't read file events", e)));
+
+        let poll = self.0.get_poll(&arg);
+        let mut run_update = true;
+        let mut stale = false;
+        let mut delayer = delay_for(Duration::from_secs(0));
+        let reason = loop {
+            if run_update {
+                let started = Instant::now();
+                let res = timeout(arg.get_deadline(), self.0.update()).await;
+                self.1.stats.update(&name, started.elapsed());
+                match res {
+                    Ok(r) => {
+                        unwrap_er!(r);
+                        self.0.export_metrics(&self.1.metrics);
+                        stale = false;
+                    },
+                    Err(_) => {
+                        log::warn!("'{}' didn't update in time, retrying later", name);
+                        if let Err(e) = output.mark_stale() {
+                            break e;
+                        }
+                        stale = true;
+                    }
+                }
+                run_update = false;
+                if let Some(p) = poll {
+                    delayer.reset(tokio::time::Instant::now() + Duration::from_secs(p));
+                } else if stale {
+                    // retry even if there is no polling
+                    delayer.reset(tokio::time::Instant::now() + arg.get_deadline());
+                }
+            }
+            if !stale {
+                let s = unwrap_er!(self.0.display(&name, &arg));
+                if let Err(e) = output.send(s) {
+                    break e;
+                }
+            }
+            let msg = select! {
+                _ = &mut delayer, if poll.is_some() || stale => {
+                    run_update = true;
+                    None
+                },
+                ev = events.next() => match ev {
+                    Some(Ok(_)) => {
+                        run_update = true;
+                        None
+                    },
+                    Some(Err(e)) => break ExitReason::error("couldn't read file events", e),
+                    None => break ExitReason::failed("can't wait on more file events"),
+                },
+                msg = from_pipo.recv() => match msg {
+                    None => break ExitReason::Normal,
+                    Some(m) => Some(m)
+                }
+            };
+            match msg {
+                // things have probably changed a lot while sleeping
+                Some(m) if m == "resume" => {
+                    self.0.on_resume();
+                    run_update = true;
+                },
+                Some(m) => {
+                    run_update = unwrap_er!(self.0.on_msg(m).await);
+                    self.1.state.set(&name, self.0.save_state());
+                },
+                None => (),
+            }
+        };
+        unwrap_er!(self.0.finalize().await);
+        reason
+    }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        self.0.uevent_subsystems()
+    }
+
+    fn wants_resume(&self, _arg: &GenArg) -> bool {
+        self.0.wants_resume()
+    }
+}
+
+#[async_trait(?Send)]
+pub trait SampleGenerator {
+    type Sample: Sample;
+    async fn init(&mut self, _arg: &GenArg) -> Result<()> {Ok(())}
+    fn update(&mut self, sample: Rc<Self::Sample>) -> Result<()>;
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>>;
+    async fn on_msg(&mut self, _msg: String) -> Result<()> {Ok(())}
+    // the sampling period, which can be shorter if someone else wants it
+    fn get_delay(&self, arg: &GenArg) -> u64 {
+        arg.timeout.unwrap_or(5)
+    }
+    // these resample immediately
+    fn uevent_subsystems(&self) -> Vec<&'static str> {vec!()}
+    // called after waking up from sleep, right before resampling, if
+    // `wants_resume`
+    fn on_resume(&mut self) {}
+    fn wants_resume(&self) -> bool {false}
+    fn save_state(&self) -> Option<String> {None}
+    fn restore_state(&mut self, _state: &str) {}
+    // called after every new sample
+    fn export_metrics(&mut self, _metrics: &Metrics) {}
+}
+
+#[async_trait(?Send)]
+impl<G: SampleGenerator> Generator for SampleWrap<G> {
+    async fn start(&mut self,
+                   mut output: Output,
+                   mut from_pipo: mpsc::Receiver<String>,
+                   arg: GenArg,
+                   name: String) -> ExitReason
+    {
+        unwrap_er!(self.0.init(&arg).await);
+        if let Some(st) = self.1.state.get(&name) {
+            self.0.restore_state(&st);
+        }
+
+        let delay = Duration::from_secs(self.0.get_delay(&arg));
+        let mut latest = self.1.sampler.subscribe::<G::Sample>(delay, arg.get_deadline());
+        // NOTE: the receiver only wakes up on new samples, so show the
+        // current one if there is any
+        let mut sampled = false;
+        let mut stale = false;
+        let current = latest.borrow().clone();
+        if let Some(s) = current {
+            let started = Instant::now();
+            unwrap_er!(self.0.update(s));
+            self.1.stats.update(&name, started.elapsed());
+            self.0.export_metrics(&self.1.metrics);
+            sampled = true;
+        }
+
+        loop {
+            if sampled {
+                let s = unwrap_er!(self.0.display(&name, &arg));
+                if let Err(e) = output.send(s).and_then(|()| if stale {output.mark_stale()} else {Ok(())}) {
+                    break e;
+                }
+            }
+            let next = select! {
+                sample = latest.recv() => match sample {
+                    Some(s) => Left(s),
+                    None => break ExitReason::failed("the sampler stopped"),
+                },
+                msg = from_pipo.recv() => match msg {
+                    None => break ExitReason::Normal,
+                    Some(m) => Right(m)
+                }
+            };
+
+            match next {
+                Left(Some(s)) => {
+                    let started = Instant::now();
+                    unwrap_er!(self.0.update(s));
+                    self.1.stats.update(&name, started.elapsed());
+                    self.0.export_metrics(&self.1.metrics);
+                    sampled = true;
+                    stale = false;
+                }
+                // the sampler is stuck
+                Left(None) => stale = true,
+                Right(m) if m.starts_with("uevent ") => self.1.sampler.resample(G::Sample::CATEGORY),
+                Right(m) if m == "resume" => {
+                    self.0.on_resume();
+                    self.1.sampler.resample(G::Sample::CATEGORY);
+                },
+                Right(m) => {
+                    unwrap_er!(self.0.on_msg(m).await);
+                    self.1.state.set(&name, self.0.save_state());
+                }
+            }
+        }
+    }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        self.0.uevent_subsystems()
+    }
+
+    fn wants_resume(&self, _arg: &GenArg) -> bool {
+        self.0.wants_resume()
+    }
+}
+
+pub fn genid_to_generator(id: GenId, shared: &Shared) -> Box<dyn Generator> {
     match id.gen {
         GenType::ECHO => Box::new(echogen::EchoGen),
-        GenType::RAM  => Box::new(TimerWrap(ramgen::RamGen::new())),
-        GenType::CPU  => Box::new(TimerWrap(cpugen::CpuGen::new())),
-        GenType::TIME => Box::new(TimerWrap(timegen::TimeGen::new())),
-        GenType::NET  => Box::new(TimerWrap(netgen::NetGen::new())),
-        GenType::DISK => Box::new(TimerWrap(diskgen::DiskGen::new())),
-        GenType::TEMP => Box::new(TimerWrap(tempgen::TempGen::new())),
-        GenType::IP   => Box::new(DBusWrap(ipgen::IpGen::new())),
-        GenType::ONE  => Box::new(onegen::OneGen::new()),
-        GenType::BAT  => Box::new(TimerWrap(batgen::BatGen::new())),
+        GenType::RAM  => Box::new(SampleWrap(ramgen::RamGen::new(), shared.clone())),
+        GenType::CPU  => Box::new(SampleWrap(cpugen::CpuGen::new(), shared.clone())),
+        GenType::TIME => Box::new(TimerWrap(timegen::TimeGen::new(), shared.clone())),
+        GenType::NET  => Box::new(SampleWrap(netgen::NetGen::new(), shared.clone())),
+        GenType::DISK => Box::new(SampleWrap(diskgen::DiskGen::new(), shared.clone())),
+        GenType::TEMP => Box::new(SampleWrap(tempgen::TempGen::new(), shared.clone())),
+        GenType::IP   => Box::new(DBusWrap(ipgen::IpGen::new(), shared.clone())),
+        GenType::ONE  => Box::new(onegen::OneGen::new(shared.stats.clone())),
+        GenType::BAT  => Box::new(FileWrap(batgen::BatGen::new(), shared.clone())),
+        GenType::LIGHT => Box::new(FileWrap(lightgen::LightGen::new(), shared.clone())),
+        GenType::STATS => Box::new(TimerWrap(statsgen::StatsGen::new(shared.stats.clone()), shared.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::spawn_local;
+    use super::super::ready::Readiness;
+    use super::super::run_local;
+
+    // shows what is in a file, and is never polled
+    struct Cat {
+        path: PathBuf,
+        content: String,
+    }
+
+    #[async_trait(?Send)]
+    impl FileGenerator for Cat {
+        fn watched_paths(&self) -> Vec<PathBuf> {
+            vec!(self.path.clone())
+        }
+
+        async fn update(&mut self) -> Result<()> {
+            self.content = tokio::fs::read_to_string(&self.path).await?;
+            Ok(())
+        }
+
+        fn display(&self, _name: &str, _arg: &GenArg) -> Result<Vec<Markup>> {
+            Ok(vec![Markup::Text(self.content.clone())])
+        }
+
+        fn get_poll(&self, _arg: &GenArg) -> Option<u64> {
+            None
+        }
+    }
+
+    // waits for the output to be `text`
+    async fn shows(latest: &mut Latest, text: &str) {
+        let want = Some(vec![Markup::Text(text.to_string())]);
+        timeout(Duration::from_secs(5), async {
+            while *latest.borrow() != want {
+                latest.recv().await;
+            }
+        }).await.unwrap_or_else(|_| panic!("never showed {:?}", text));
+    }
+
+    fn shared() -> Shared {
+        Shared {
+            sampler: Sampler::new(Stats::new()),
+            state: StateStore::disabled(),
+            stats: Stats::new(),
+            metrics: Metrics::new(),
+        }
+    }
+
+    #[test]
+    fn only_some_generators_want_resume() {
+        let shared = shared();
+        let wants = |typ, arg: &GenArg| genid_to_generator(GenId::from_gen(typ), &shared).wants_resume(arg);
+        let empty = GenArg::empty();
+        assert!(wants(GenType::NET, &empty));
+        assert!(wants(GenType::TIME, &empty));
+        assert!(!wants(GenType::CPU, &empty));
+        assert!(!wants(GenType::ECHO, &empty));
+        assert!(!wants(GenType::BAT, &empty));
+
+        assert!(!wants(GenType::ONE, &empty));
+        let restarting = GenArg {restart_on_resume: true, ..GenArg::empty()};
+        assert!(wants(GenType::ONE, &restarting));
+    }
+
+    #[test]
+    fn unchanged_output_isnt_sent() {
+        run_local(async {
+            let (mut output, mut latest) = Output::channel(Readiness::new().token());
+            let text = |t: &str| vec![Markup::Text(t.to_string())];
+            // the initial value
+            latest.recv().await;
+
+            output.send(text("a")).unwrap();
+            assert_eq!(latest.recv().await, Some(Some(text("a"))));
+            output.send(text("a")).unwrap();
+            output.send(text("b")).unwrap();
+            assert_eq!(latest.recv().await, Some(Some(text("b"))));
+            output.send(text("b")).unwrap();
+            assert!(timeout(Duration::from_millis(50), latest.recv()).await.is_err());
+        });
+    }
+
+    #[test]
+    fn file_writes_trigger_updates() {
+        let path = std::env::temp_dir().join(format!("statusbar-filewrap-{}", std::process::id()));
+        std::fs::write(&path, "before").unwrap();
+
+        run_local(async {
+            let mut gen = FileWrap(Cat {path: path.clone(), content: String::new()}, shared());
+            let (output, mut latest) = Output::channel(Readiness::new().token());
+            let (_to_gen, from_pipo) = mpsc::channel(1);
+            spawn_local(async move {
+                gen.start(output, from_pipo, GenArg::empty(), "cat".to_string()).await
+            });
+
+            shows(&mut latest, "before").await;
+            tokio::fs::write(&path, "after").await.unwrap();
+            shows(&mut latest, "after").await;
+        });
+
+        std::fs::remove_file(&path).unwrap();
     }
 }
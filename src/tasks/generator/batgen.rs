@@ -1,9 +1,9 @@
 use async_trait::async_trait;
-use super::{TimerGenerator,GenArg,Result,ExitReason};
-use std::path::Path;
+use super::{FileGenerator,GenArg,Result,ExitReason};
+use std::path::{Path,PathBuf};
 use tokio::fs;
-
-// TODO: använd udev för att lyssna på om den börjar ladda elr inte.
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
 
 const CAP_FILE:    &str = "/sys/class/power_supply/BAT0/capacity";
 const STATUS_FILE: &str = "/sys/class/power_supply/BAT0/status";
@@ -22,8 +22,8 @@ impl BatGen {
     }
 }
 
-#[async_trait]
-impl TimerGenerator for BatGen {
+#[async_trait(?Send)]
+impl FileGenerator for BatGen {
     async fn init(&mut self, _arg: &GenArg) -> Result<()> {
         if !Path::new(CAP_FILE).exists() {
             log::warn!("couldn't find a battery");
@@ -33,6 +33,10 @@ impl TimerGenerator for BatGen {
         Ok(())
     }
 
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        vec!(PathBuf::from(CAP_FILE), PathBuf::from(STATUS_FILE))
+    }
+
     async fn update(&mut self) -> Result<()> {
         let cap = fs::read_to_string(CAP_FILE).await?;
         self.capacity = match cap.trim_end().parse() {
@@ -52,7 +56,7 @@ impl TimerGenerator for BatGen {
         Ok(())
     }
 
-    fn display(&self, _name: &str, arg: &GenArg) -> Result<String> {
+    fn display(&self, _name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
         let mut s = arg.get_builder()
             .add(self.capacity.to_string())
             .add("%");
@@ -63,6 +67,27 @@ impl TimerGenerator for BatGen {
             s = s.color_step(self.capacity as i32, &[(0, "red"), (16, "yellow"), (30, "fg")])
         }
 
-        Ok(s.to_string())
+        Ok(s.into_markup())
+    }
+
+    async fn on_msg(&mut self, msg: String) -> Result<bool> {
+        Ok(msg.starts_with("uevent "))
+    }
+
+    // charging changes are noticed through uevents, so the capacity
+    // doesn't need to be polled that often
+    fn get_poll(&self, arg: &GenArg) -> Option<u64> {
+        Some(arg.timeout.unwrap_or(30))
+    }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        vec!("power_supply")
+    }
+
+    fn export_metrics(&mut self, m: &Metrics) {
+        if self.capacity != u8::MAX {
+            m.set("battery_capacity_percent", "Battery charge", &[], self.capacity as f64);
+        }
+        m.set("battery_charging", "1 if charging or full", &[], if self.charging {1.0} else {0.0});
     }
 }
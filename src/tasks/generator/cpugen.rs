@@ -1,19 +1,24 @@
-use sysinfo::{SystemExt,ProcessorExt};
 use async_trait::async_trait;
-use super::{TimerGenerator,GenArg,Result};
+use std::rc::Rc;
+use super::{SampleGenerator,GenArg,Result};
+use crate::tasks::sampler::CpuSample;
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
 
 const LEVELS: &[(i32, &str)] = &[(50, "yellow"), (75, "red")];
 
-pub struct CpuGen{sys: sysinfo::System, detailed: bool}
+pub struct CpuGen{sample: Option<Rc<CpuSample>>, detailed: bool}
 
 impl CpuGen {
     pub fn new() -> Self {
-        CpuGen{sys: sysinfo::System::new(), detailed: false}
+        CpuGen{sample: None, detailed: false}
     }
 }
 
-#[async_trait]
-impl TimerGenerator for CpuGen {
+#[async_trait(?Send)]
+impl SampleGenerator for CpuGen {
+    type Sample = CpuSample;
+
     async fn init(&mut self, arg: &GenArg) -> Result<()> {
         if let Some(a) = &arg.arg {
             if a == "detailed" {
@@ -23,16 +28,21 @@ impl TimerGenerator for CpuGen {
         Ok(())
     }
 
-    async fn update(&mut self) -> Result<()> {
-        self.sys.refresh_cpu();
+    fn update(&mut self, sample: Rc<CpuSample>) -> Result<()> {
+        self.sample = Some(sample);
         Ok(())
     }
 
-    fn display(&self, name: &str, arg: &GenArg) -> Result<String> {
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return Ok(Vec::new()),
+        };
+
         if self.detailed {
             let mut bu = arg.get_builder().new_section();
-            for p in self.sys.get_processors() {
-                let usage = p.get_cpu_usage().round();
+            for p in sample.cores.iter() {
+                let usage = p.round();
                 bu = bu.add_not_empty("/")
                     .new_section()
                     .add(format!("{:0>2}", usage))
@@ -40,25 +50,47 @@ impl TimerGenerator for CpuGen {
             }
             Ok(bu.everything()
                .name_click(1, name)
-               .to_string())
+               .into_markup())
         } else {
-            let usage = self.sys.get_global_processor_info().get_cpu_usage().round();
+            let usage = sample.global.round();
 
             Ok(arg.get_builder()
                .add(usage.to_string())
                .add("%")
                .color_step(usage as i32, LEVELS)
                .name_click(1, name)
-               .to_string())
+               .into_markup())
         }
     }
 
-    async fn on_msg(&mut self, _msg: String) -> Result<bool> {
-        self.detailed = !self.detailed;
-        Ok(false)
+    async fn on_msg(&mut self, msg: String) -> Result<()> {
+        if msg == "click 1" {
+            self.detailed = !self.detailed;
+        }
+        Ok(())
     }
 
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(2)
     }
+
+    fn save_state(&self) -> Option<String> {
+        Some(if self.detailed {"detailed"} else {"global"}.to_string())
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        self.detailed = state == "detailed";
+    }
+
+    fn export_metrics(&mut self, m: &Metrics) {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return,
+        };
+        const HELP: &str = "CPU usage in percent";
+        m.set("cpu_usage_percent", HELP, &[("core", "all")], sample.global as f64);
+        for (i, p) in sample.cores.iter().enumerate() {
+            m.set("cpu_usage_percent", HELP, &[("core", &i.to_string())], *p as f64);
+        }
+    }
 }
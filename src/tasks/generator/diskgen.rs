@@ -1,27 +1,39 @@
-use sysinfo::{SystemExt,DiskExt};
 use async_trait::async_trait;
 use std::path::PathBuf;
-use super::{TimerGenerator,GenArg,Result,ExitReason};
+use std::rc::Rc;
+use super::{SampleGenerator,GenArg,Result,ExitReason};
+use crate::tasks::sampler::{DiskSample,DiskData};
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
 
 const LEVELS: &[(i32, &str)] = &[(90, "yellow"), (95, "red")];
 const FS_WHITELIST: &[&str] = &["nfs", "ext4"];
 
+fn is_ours(disks: &[PathBuf], disk: &DiskData) -> bool {
+    FS_WHITELIST.contains(&disk.file_system.as_str()) && disks.contains(&disk.mount_point)
+}
+
 pub struct DiskGen{
-    sys: sysinfo::System,
+    sample: Option<Rc<DiskSample>>,
     disks: Vec<PathBuf>,
+    // mount points in the metrics
+    exported: Vec<String>,
 }
 
 impl DiskGen {
     pub fn new() -> Self {
         DiskGen{
-            sys: sysinfo::System::new(),
+            sample: None,
             disks: Vec::new(),
+            exported: Vec::new(),
         }
     }
 }
 
-#[async_trait]
-impl TimerGenerator for DiskGen {
+#[async_trait(?Send)]
+impl SampleGenerator for DiskGen {
+    type Sample = DiskSample;
+
     async fn init(&mut self, arg: &GenArg) -> Result<()> {
         if let Some(a) = &arg.arg {
             for disk in a.split(",") {
@@ -37,31 +49,29 @@ impl TimerGenerator for DiskGen {
         Ok(())
     }
 
-    async fn update(&mut self) -> Result<()> {
-        self.sys.refresh_disks_list();
-        self.sys.refresh_disks();
+    fn update(&mut self, sample: Rc<DiskSample>) -> Result<()> {
+        self.sample = Some(sample);
         Ok(())
     }
 
-    fn display(&self, _name: &str, arg: &GenArg) -> Result<String> {
+    fn display(&self, _name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return Ok(Vec::new()),
+        };
+
         let mut bu = arg.get_builder().new_section();
         let mut missing = self.disks.len();
 
-        for disk in self.sys.get_disks() {
-
-            let correct_fs = std::str::from_utf8(disk.get_file_system())
-                .map_or(false, |fs| FS_WHITELIST.contains(&fs));
-
-            let our_disk = self.disks.iter().any(|p| p == disk.get_mount_point());
-
-            if !correct_fs || !our_disk {
+        for disk in sample.disks.iter() {
+            if !is_ours(&self.disks, disk) {
                 continue;
             }
 
             missing -= 1;
 
-            let total = disk.get_total_space();
-            let avail = disk.get_available_space();
+            let total = disk.total;
+            let avail = disk.available;
             let used = total - avail;
             let perc = ((used as f64 / total as f64) * 100.0).round() as i32;
 
@@ -77,10 +87,33 @@ impl TimerGenerator for DiskGen {
                 .add("xx");
         }
 
-        Ok(bu.to_string())
+        Ok(bu.into_markup())
     }
 
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(60)
     }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        vec!("block")
+    }
+
+    fn export_metrics(&mut self, m: &Metrics) {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return,
+        };
+        let mut mounts = Vec::new();
+        for disk in sample.disks.iter().filter(|d| is_ours(&self.disks, d)) {
+            let mount = disk.mount_point.to_string_lossy().into_owned();
+            m.set("disk_total_bytes", "Size of the file system", &[("mount", &mount)], disk.total as f64);
+            m.set("disk_available_bytes", "Free space on the file system", &[("mount", &mount)], disk.available as f64);
+            mounts.push(mount);
+        }
+        for gone in self.exported.iter().filter(|e| !mounts.contains(e)) {
+            m.remove("disk_total_bytes", &[("mount", gone)]);
+            m.remove("disk_available_bytes", &[("mount", gone)]);
+        }
+        self.exported = mounts;
+    }
 }
@@ -1,6 +1,5 @@
 use tokio;
 use tokio::sync::mpsc;
-use tokio::sync::broadcast;
 use async_trait::async_trait;
 use super::*;
 use crate::tasks::ExitReason;
@@ -8,20 +7,20 @@ use crate::dzen_format::external::fix_dzen_string;
 
 pub struct EchoGen;
 
-#[async_trait]
+#[async_trait(?Send)]
 impl Generator for EchoGen {
     async fn start(&mut self,
-                   to_printer: broadcast::Sender<Msg>,
+                   mut output: Output,
                    mut from_pipo: mpsc::Receiver<String>,
-                   id: GenId,
                    arg: GenArg,
                    _name: String) -> ExitReason
     {
+        output.ready();
         while let Some(inp) = from_pipo.recv().await {
             let fixed = fix_dzen_string(inp);
-            let s = arg.get_builder().add(fixed).to_string();
-            if let Err(_) = to_printer.send(Msg::Gen(id, s)) {
-                return ExitReason::Error;
+            let s = arg.get_builder().append(fixed).into_markup();
+            if output.send(s).is_err() {
+                return ExitReason::failed("the bar stopped listening");
             }
         }
         ExitReason::Normal
@@ -11,6 +11,7 @@ use std::collections::HashMap;
 use simple_error::SimpleError;
 use dbus::arg::RefArg;
 use dbus_tokio::connection::IOResource;
+use crate::dzen_format::markup::Markup;
 
 // https://developer.gnome.org/NetworkManager/stable/index.html
 
@@ -122,7 +123,7 @@ async fn get_string<C>(
     name: &str,
     conn: Arc<C>,
     arg: &GenArg
-) -> String
+) -> Vec<Markup>
 where C: DN::NonblockReply
 {
     let to_show;
@@ -176,10 +177,10 @@ where C: DN::NonblockReply
         }
     }
 
-    bu.to_string()
+    bu.into_markup()
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 impl DBusGenerator for IpGen {
     fn get_connection(&self) -> EResult<(IOResource<DN::SyncConnection>, Arc<DN::SyncConnection>)> {
         Ok(connection::new_system_sync()?)
@@ -202,7 +203,7 @@ impl DBusGenerator for IpGen {
         Ok(())
     }
 
-    async fn update(&mut self, conn: Arc<DN::SyncConnection>, name: &str, arg: &GenArg) -> EResult<String> {
+    async fn update(&mut self, conn: Arc<DN::SyncConnection>, name: &str, arg: &GenArg) -> EResult<Vec<Markup>> {
         Ok(get_string(&self.interface, self.state, self.show_ssid, name, conn.clone(), arg).await)
     }
 
@@ -226,4 +227,12 @@ impl DBusGenerator for IpGen {
         }
         Ok(())
     }
+
+    fn save_state(&self) -> Option<String> {
+        Some(if self.show_ssid {"ssid"} else {"ip"}.to_string())
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        self.show_ssid = state == "ssid";
+    }
 }
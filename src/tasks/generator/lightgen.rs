@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use super::{FileGenerator,GenArg,Result,ExitReason};
+use std::path::PathBuf;
+use tokio::fs;
+use crate::dzen_format::markup::Markup;
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+pub struct LightGen {
+    device: PathBuf,
+    brightness: u64,
+    max: u64,
+    step: u64,
+}
+
+impl LightGen {
+    pub fn new() -> Self {
+        LightGen{
+            device: PathBuf::new(),
+            brightness: 0,
+            max: 1,
+            step: 5,
+        }
+    }
+
+    fn percent(&self) -> u64 {
+        ((self.brightness as f64 / self.max as f64) * 100.0).round() as u64
+    }
+
+    // the brightness one step up or down, which moves at least one unit
+    // even if the step is smaller than that
+    fn stepped(&self, up: bool) -> u64 {
+        let perc = self.percent();
+        let perc = if up {perc + self.step} else {perc.saturating_sub(self.step)};
+        let target = (self.max as f64 * perc as f64 / 100.0).round() as u64;
+        let target = if up {
+            std::cmp::max(target, self.brightness + 1)
+        } else {
+            std::cmp::min(target, self.brightness.saturating_sub(1))
+        };
+        std::cmp::min(target, self.max)
+    }
+
+    async fn set_brightness(&mut self, new: u64) -> Result<()> {
+        // NOTE: requires write access to the file, usually given by a
+        // udev rule to the video group
+        if let Err(e) = fs::write(self.device.join("brightness"), new.to_string()).await {
+            log::warn!("couldn't set brightness because '{}'", e);
+        }
+        Ok(())
+    }
+}
+
+async fn read_number(path: PathBuf) -> Result<u64> {
+    let s = fs::read_to_string(&path).await?;
+    match s.trim_end().parse() {
+        Ok(n) => Ok(n),
+        Err(e) => Err(ExitReason::error(format!("couldn't parse {:?}", path), e)),
+    }
+}
+
+#[async_trait(?Send)]
+impl FileGenerator for LightGen {
+    async fn init(&mut self, arg: &GenArg) -> Result<()> {
+        self.device = if let Some(dev) = &arg.arg {
+            PathBuf::from(BACKLIGHT_DIR).join(dev)
+        } else {
+            match fs::read_dir(BACKLIGHT_DIR).await?.next_entry().await? {
+                Some(entry) => entry.path(),
+                None => {
+                    log::warn!("couldn't find a backlight");
+                    return Err(ExitReason::NonFatal);
+                }
+            }
+        };
+
+        if !self.device.join("brightness").exists() {
+            log::warn!("{:?} is not a backlight", self.device);
+            return Err(ExitReason::NonFatal);
+        }
+
+        self.max = std::cmp::max(1, read_number(self.device.join("max_brightness")).await?);
+        if let Some(step) = arg.step {
+            self.step = step;
+        }
+        Ok(())
+    }
+
+    fn watched_paths(&self) -> Vec<PathBuf> {
+        vec!(self.device.join("brightness"))
+    }
+
+    async fn update(&mut self) -> Result<()> {
+        self.brightness = read_number(self.device.join("brightness")).await?;
+        Ok(())
+    }
+
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        Ok(arg.get_builder()
+           .add(self.percent().to_string())
+           .add("%")
+           .name_scroll(name)
+           .into_markup())
+    }
+
+    async fn on_msg(&mut self, msg: String) -> Result<bool> {
+        match msg.as_str() {
+            "scroll up" => self.set_brightness(self.stepped(true)).await?,
+            "scroll down" => self.set_brightness(self.stepped(false)).await?,
+            m if m.starts_with("uevent ") => (),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn get_poll(&self, arg: &GenArg) -> Option<u64> {
+        Some(arg.timeout.unwrap_or(10))
+    }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        vec!("backlight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(brightness: u64, max: u64, step: u64) -> LightGen {
+        LightGen {brightness, max, step, ..LightGen::new()}
+    }
+
+    #[test]
+    fn coarse_steps_still_move() {
+        // 57% + 5% rounds back to 4
+        assert_eq!(light(4, 7, 5).stepped(true), 5);
+        assert_eq!(light(4, 7, 5).stepped(false), 3);
+    }
+
+    #[test]
+    fn fine_steps_go_by_percent() {
+        assert_eq!(light(500, 1000, 5).stepped(true), 550);
+        assert_eq!(light(500, 1000, 5).stepped(false), 450);
+    }
+
+    #[test]
+    fn stays_in_range() {
+        assert_eq!(light(7, 7, 5).stepped(true), 7);
+        assert_eq!(light(0, 7, 5).stepped(false), 0);
+        assert_eq!(light(980, 1000, 5).stepped(true), 1000);
+    }
+}
@@ -1,40 +1,48 @@
-use sysinfo::{SystemExt,NetworkExt};
-use std::collections::HashSet;
 use async_trait::async_trait;
-use super::{TimerGenerator,GenArg,Result,ExitReason};
+use std::path::Path;
+use std::rc::Rc;
+use super::{SampleGenerator,GenArg,Result,ExitReason};
+use crate::tasks::sampler::NetSample;
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
+
+const NET_DIR: &str = "/sys/class/net";
 
 pub struct NetGen{
-    sys: sysinfo::System,
+    sample: Option<Rc<NetSample>>,
     interfaces: Vec<String>,
     cur_if: usize,
     total: bool,
-    timeout: u64
+    // the sample right after a resume spans the whole sleep, so its
+    // rates are garbage
+    resumed: bool,
+    stale_rates: bool,
+    // interfaces in the metrics
+    exported: Vec<String>,
 }
 
 impl NetGen {
     pub fn new() -> Self {
         NetGen{
-            sys: sysinfo::System::new(),
+            sample: None,
             interfaces: Vec::new(),
             cur_if: 0,
             total: false,
-            timeout: 0
+            resumed: false,
+            stale_rates: false,
+            exported: Vec::new(),
         }
     }
 }
 
-#[async_trait]
-impl TimerGenerator for NetGen {
-    async fn init(&mut self, arg: &GenArg) -> Result<()> {
-        self.sys.refresh_networks_list();
-        let avail_net = self.sys.get_networks()
-            .into_iter()
-            .map(|(x, _)| x.as_str())
-            .collect::<HashSet<&str>>();
+#[async_trait(?Send)]
+impl SampleGenerator for NetGen {
+    type Sample = NetSample;
 
+    async fn init(&mut self, arg: &GenArg) -> Result<()> {
         if let Some(a) = &arg.arg {
             for iface in a.split(" ") {
-                if !avail_net.contains(iface) {
+                if !Path::new(NET_DIR).join(iface).exists() {
                     log::warn!("{} is not a connected interface", iface);
                 }
                 self.interfaces.push(iface.to_string());
@@ -46,28 +54,36 @@ impl TimerGenerator for NetGen {
             return Err(ExitReason::NonFatal);
         }
 
-        self.timeout = self.get_delay(arg);
-
         Ok(())
     }
 
-    async fn update(&mut self) -> Result<()> {
-        self.sys.refresh_networks();
+    fn update(&mut self, sample: Rc<NetSample>) -> Result<()> {
+        self.stale_rates = self.resumed;
+        self.resumed = false;
+        self.sample = Some(sample);
         Ok(())
     }
 
-    fn display(&self, name: &str, arg: &GenArg) -> Result<String> {
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return Ok(Vec::new()),
+        };
+
         let cur_if = self.interfaces[self.cur_if].as_str();
-        let net = self.sys.get_networks()
-            .into_iter()
-            .find(|(iface, _)| *iface == cur_if)
-            .map(|(_, n)| n)
-            .ok_or(ExitReason::Error)?;
-
-        let (up, down) = if !self.total {
-            (net.get_transmitted() / self.timeout, net.get_received() / self.timeout)
+        let net = sample.interfaces
+            .iter()
+            .find(|n| n.name == cur_if)
+            .ok_or_else(|| ExitReason::failed(format!("interface {} is gone", cur_if)))?;
+
+        let (up, down) = if self.stale_rates && !self.total {
+            (0, 0)
+        } else if !self.total {
+            let secs = sample.elapsed.as_secs_f64();
+            let rate = |b: u64| if secs > 0.0 {(b as f64 / secs) as u64} else {0};
+            (rate(net.transmitted), rate(net.received))
         } else {
-            (net.get_total_transmitted(), net.get_total_received())
+            (net.total_transmitted, net.total_received)
         };
 
         let o = arg.get_builder()
@@ -78,12 +94,22 @@ impl TimerGenerator for NetGen {
             .maybe_add(!self.total, "/s")
             .name_click(1, name)
             .name_click(3, name)
-            .to_string();
+            .into_markup();
 
         Ok(o)
     }
 
-    async fn on_msg(&mut self, msg: String) -> Result<bool> {
+    // the rates span the whole sleep
+    fn wants_resume(&self) -> bool {
+        true
+    }
+
+    fn on_resume(&mut self) {
+        self.resumed = true;
+        self.stale_rates = true;
+    }
+
+    async fn on_msg(&mut self, msg: String) -> Result<()> {
         match msg.as_str() {
             "click 3" => {
                 self.cur_if += 1;
@@ -96,10 +122,58 @@ impl TimerGenerator for NetGen {
                 log::info!("got unexpected message");
             }
         }
-        Ok(false)
+        Ok(())
     }
 
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(2)
     }
+
+    fn uevent_subsystems(&self) -> Vec<&'static str> {
+        vec!("net")
+    }
+
+    // `<interface> total` or `<interface> rate`
+    fn save_state(&self) -> Option<String> {
+        Some(format!("{} {}", self.interfaces[self.cur_if], if self.total {"total"} else {"rate"}))
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        let mut words = state.split(' ');
+        if let Some(i) = words.next().and_then(|iface| self.interfaces.iter().position(|x| x == iface)) {
+            self.cur_if = i;
+        }
+        self.total = words.next() == Some("total");
+    }
+
+    fn export_metrics(&mut self, m: &Metrics) {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return,
+        };
+        let secs = sample.elapsed.as_secs_f64();
+        let ours = sample.interfaces.iter().filter(|n| self.interfaces.contains(&n.name));
+        for net in ours {
+            let l = &[("interface", net.name.as_str())];
+            if !self.stale_rates && secs > 0.0 {
+                m.set("net_transmit_bytes_per_second", "Upload rate", l, net.transmitted as f64 / secs);
+                m.set("net_receive_bytes_per_second", "Download rate", l, net.received as f64 / secs);
+            }
+            m.set("net_transmitted_bytes", "Uploaded since boot", l, net.total_transmitted as f64);
+            m.set("net_received_bytes", "Downloaded since boot", l, net.total_received as f64);
+        }
+
+        let present: Vec<String> = sample.interfaces.iter()
+            .filter(|n| self.interfaces.contains(&n.name))
+            .map(|n| n.name.clone())
+            .collect();
+        for gone in self.exported.iter().filter(|e| !present.contains(e)) {
+            let l = &[("interface", gone.as_str())];
+            m.remove("net_transmit_bytes_per_second", l);
+            m.remove("net_receive_bytes_per_second", l);
+            m.remove("net_transmitted_bytes", l);
+            m.remove("net_received_bytes", l);
+        }
+        self.exported = present;
+    }
 }
@@ -1,25 +1,30 @@
 use tokio;
-use tokio::process::Command;
+use tokio::process::{ChildStdin, Command};
 use tokio::io::BufReader;
 use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc;
-use tokio::sync::broadcast;
 use async_trait::async_trait;
 use crate::kill::ChildTerminator;
 use super::*;
 use crate::tasks::ExitReason;
 use crate::dzen_format::external::fix_dzen_string;
 
-pub struct OneGen;
+use crate::tasks::stats::Stats;
+
+// messages for the script that haven't been written yet
+const STDIN_QUEUE: usize = 8;
+
+pub struct OneGen(Stats);
 
 impl OneGen {
-    pub fn new() -> Self {
-        OneGen
+    pub fn new(stats: Stats) -> Self {
+        OneGen(stats)
     }
 }
 
 // TODO: move this to a more sensible location
-pub fn spawn(cmd: &str, first: bool) -> std::io::Result<ChildTerminator> {
+pub fn spawn(cmd: &str, first: bool, step: Option<u64>) -> std::io::Result<ChildTerminator> {
     let mut path = std::env::var("PATH").expect("couldn't get PATH");
     path.insert_str(0, ":");
     path.insert_str(0, crate::config::SCRIPT_PATH);
@@ -27,24 +32,46 @@ pub fn spawn(cmd: &str, first: bool) -> std::io::Result<ChildTerminator> {
         path.replace_range(..1, &std::env::var("HOME").expect("couldn't get HOME"));
     }
 
-    Command::new("sh")
-        .arg("-c")
+    let mut command = Command::new("sh");
+    command.arg("-c")
         .arg(cmd)
         .env("PATH", path)
         .env("STS_INIT", if first {"yes"} else {""})
         .kill_on_drop(false)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
+        .stdout(std::process::Stdio::piped());
+
+    // scripts that want scroll events get them on stdin
+    if let Some(s) = step {
+        command.env("STS_STEP", s.to_string())
+            .stdin(std::process::Stdio::piped());
+    }
+
+    command.spawn()
         .map(|c| ChildTerminator::new(c))
 }
 
-#[async_trait]
+// Writes the messages to `stdin` in a task of its own, so a script that
+// doesn't read them can't block us. Messages that don't fit in the
+// queue are dropped by the sender.
+fn forward(mut stdin: ChildStdin, cmd: String) -> mpsc::Sender<String> {
+    let (send, mut recv) = mpsc::channel::<String>(STDIN_QUEUE);
+    tokio::task::spawn_local(async move {
+        while let Some(m) = recv.recv().await {
+            if let Err(e) = stdin.write_all(m.as_bytes()).await {
+                log::warn!("couldn't forward message to '{}' because {}", cmd, e);
+                break;
+            }
+        }
+    });
+    send
+}
+
+#[async_trait(?Send)]
 impl Generator for OneGen {
     async fn start(
         &mut self,
-        to_printer: broadcast::Sender<Msg>,
+        mut output: Output,
         mut from_pipo: mpsc::Receiver<String>,
-        id: GenId,
         arg: GenArg,
         name: String
     ) -> ExitReason
@@ -53,22 +80,23 @@ impl Generator for OneGen {
             if let Some(cmd) = &arg.arg {
                 cmd.to_string()
             } else {
-                log::error!("I want a command as argument");
-                return ExitReason::Error;
+                return ExitReason::failed("I want a command as argument");
             };
-
         let mut first = true;
         loop {
+            let mut restart = false;
             // start process
-            let mut proc = match spawn(&cmd, first) {
+            let mut proc = match spawn(&cmd, first, arg.step) {
                 Ok(c) => c,
-                Err(e) => {
-                    log::error!("{}", e);
-                    return ExitReason::Error;
-                }
+                Err(e) => return ExitReason::error(format!("couldn't start '{}'", cmd), e),
             };
+            let mut sin = proc.as_mut_ref().stdin.take().map(|s| forward(s, cmd.clone()));
             let mut sout = BufReader::new(proc.as_mut_ref().stdout.as_mut().unwrap()).lines();
+            if !first {
+                self.0.restart(&name);
+            }
             first = false;
+            output.ready();
 
             // read lines until there are no more
             let (term, er) = loop {
@@ -79,7 +107,21 @@ impl Generator for OneGen {
                     x = from_pipo.recv() => {
                         match x {
                             None => break (true, Some(ExitReason::Normal)),
-                            Some(_) => None
+                            // only sent with `restart_on_resume`
+                            Some(m) if m == "resume" => {
+                                restart = true;
+                                break (true, None);
+                            }
+                            Some(m) => {
+                                if let Some(stdin) = sin.as_mut() {
+                                    match stdin.try_send(m + "\n") {
+                                        Ok(()) => (),
+                                        Err(mpsc::error::TrySendError::Full(_)) => self.0.dropped(&name),
+                                        Err(mpsc::error::TrySendError::Closed(_)) => sin = None,
+                                    }
+                                }
+                                None
+                            }
                         }
                     }
                 };
@@ -91,23 +133,24 @@ impl Generator for OneGen {
 
                             let clicked = if !fixed.is_empty() {
                                 arg.get_builder()
-                                    .add(fixed)
+                                    .append(fixed)
+                                    .guard(arg.step.is_some(), |b| b.name_scroll(&name))
                                     .name_click(1, &name)
-                                    .to_string()
+                                    .into_markup()
                             } else {
                                 fixed
                             };
 
-                            if let Err(_) = to_printer.send(Msg::Gen(id, clicked)) {
-                                break (true, Some(ExitReason::Error));
+                            if output.send(clicked).is_err() {
+                                break (true, Some(ExitReason::failed("the bar stopped listening")));
                             }
                         }
                         Ok(None) => {
                             break (false, None);
                         }
                         Err(e) => {
-                            log::error!("{}", e);
-                            break (true, Some(ExitReason::Error));
+                            let er = ExitReason::error(format!("couldn't read from '{}'", cmd), e);
+                            break (true, Some(er));
                         }
                     }
                 }
@@ -130,6 +173,10 @@ impl Generator for OneGen {
                 break e;
             }
 
+            if restart {
+                continue;
+            }
+
             // wait for someone to click on us
             match from_pipo.recv().await {
                 None => break ExitReason::Normal,
@@ -137,4 +184,38 @@ impl Generator for OneGen {
             }
         }
     }
+
+    fn wants_resume(&self, arg: &GenArg) -> bool {
+        arg.restart_on_resume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::run_local;
+
+    #[test]
+    fn scripts_that_dont_read_cant_block() {
+        run_local(async {
+            let mut child = Command::new("sleep")
+                .arg("5")
+                .kill_on_drop(true)
+                .stdin(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            let mut sin = forward(child.stdin.take().unwrap(), "sleep".to_string());
+
+            // way more than the pipe holds
+            let line = "x".repeat(4096) + "\n";
+            let mut dropped = 0;
+            for _ in 0..100 {
+                if let Err(mpsc::error::TrySendError::Full(_)) = sin.try_send(line.clone()) {
+                    dropped += 1;
+                }
+                tokio::time::delay_for(std::time::Duration::from_millis(1)).await;
+            }
+            assert!(dropped > 0);
+        });
+    }
 }
@@ -1,34 +1,44 @@
-use sysinfo::SystemExt;
 use async_trait::async_trait;
-use super::{Result,TimerGenerator,GenArg};
+use std::rc::Rc;
+use super::{Result,SampleGenerator,GenArg};
+use crate::tasks::sampler::MemSample;
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
 
 const LEVELS: &[(i32, &str)] = &[(60, "yellow"), (80, "red")];
 
-pub struct RamGen{sys: sysinfo::System}
+pub struct RamGen{sample: Option<Rc<MemSample>>}
 
 impl RamGen {
     pub fn new() -> Self {
-        RamGen{sys: sysinfo::System::new()}
+        RamGen{sample: None}
     }
 }
 
-#[async_trait]
-impl TimerGenerator for RamGen {
-    async fn update(&mut self) -> Result<()> {
-        self.sys.refresh_memory();
+#[async_trait(?Send)]
+impl SampleGenerator for RamGen {
+    type Sample = MemSample;
+
+    fn update(&mut self, sample: Rc<MemSample>) -> Result<()> {
+        self.sample = Some(sample);
         Ok(())
     }
 
-    fn display(&self, _name: &str, arg: &GenArg) -> Result<String> {
-        let usage = ((self.sys.get_used_memory() as f64 / self.sys.get_total_memory() as f64) * 100.0).round();
+    fn display(&self, _name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return Ok(Vec::new()),
+        };
+
+        let usage = ((sample.used as f64 / sample.total as f64) * 100.0).round();
 
         let mut bu = arg.get_builder()
             .add(usage.to_string())
             .add("%")
             .color_step(usage as i32, LEVELS);
 
-        let swap = self.sys.get_used_swap();
-        let total_swap = self.sys.get_total_swap() as f64;
+        let swap = sample.used_swap;
+        let total_swap = sample.total_swap as f64;
         let swap_perc = ((swap as f64 / total_swap) * 100.0).round() as i32;
         if swap_perc > 0 {
             bu = bu.add(" (")
@@ -38,12 +48,22 @@ impl TimerGenerator for RamGen {
                 .add(")");
         }
 
-        Ok(bu.to_string())
+        Ok(bu.into_markup())
     }
 
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(2)
     }
+
+    // sysinfo counts in KiB
+    fn export_metrics(&mut self, m: &Metrics) {
+        if let Some(s) = &self.sample {
+            m.set("memory_used_bytes", "Used RAM", &[], (s.used * 1024) as f64);
+            m.set("memory_total_bytes", "Total RAM", &[], (s.total * 1024) as f64);
+            m.set("swap_used_bytes", "Used swap", &[], (s.used_swap * 1024) as f64);
+            m.set("swap_total_bytes", "Total swap", &[], (s.total_swap * 1024) as f64);
+        }
+    }
 }
 
 
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use super::{TimerGenerator,GenArg,Result};
+use crate::tasks::stats::{Stats,Usage,GenStats};
+use crate::dzen_format::markup::Markup;
+
+// how much the bar itself costs, or what the most expensive generator is
+pub struct StatsGen {
+    stats: Stats,
+    usage: Usage,
+    top: Option<(String, GenStats)>,
+    show_top: bool,
+}
+
+impl StatsGen {
+    pub fn new(stats: Stats) -> Self {
+        StatsGen{
+            stats,
+            usage: Usage::default(),
+            top: None,
+            show_top: false,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl TimerGenerator for StatsGen {
+    async fn update(&mut self) -> Result<()> {
+        self.usage = self.stats.usage();
+        self.top = self.stats.most_expensive();
+        Ok(())
+    }
+
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let b = arg.get_builder();
+        let b = match &self.top {
+            Some((gen, g)) if self.show_top => b
+                .add(gen.as_str())
+                .add(format!(" {}ms", g.update_time.as_millis())),
+            _ => b
+                .add(format!("{:.1}%", self.usage.cpu_percent))
+                .add(" ")
+                .add_ibibyte(self.usage.rss),
+        };
+        Ok(b.name_click(1, name).into_markup())
+    }
+
+    async fn on_msg(&mut self, msg: String) -> Result<bool> {
+        if msg == "click 1" {
+            self.show_top = !self.show_top;
+        }
+        Ok(false)
+    }
+
+    fn save_state(&self) -> Option<String> {
+        Some(if self.show_top {"top"} else {"usage"}.to_string())
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        self.show_top = state == "top";
+    }
+}
@@ -1,29 +1,37 @@
 use sysinfo::{SystemExt,ComponentExt};
 use std::collections::HashSet;
+use std::rc::Rc;
 use async_trait::async_trait;
-use super::{TimerGenerator,GenArg,Result,ExitReason};
+use super::{SampleGenerator,GenArg,Result,ExitReason};
+use crate::tasks::sampler::TempSample;
+use crate::tasks::metrics::Metrics;
+use crate::dzen_format::markup::Markup;
 
 const LEVELS: &[(i32, &str)] = &[(50, "yellow"), (70, "red")];
 
 pub struct TempGen {
-    sys: sysinfo::System,
+    sample: Option<Rc<TempSample>>,
     name: String
 }
 
 impl TempGen {
     pub fn new() -> Self {
         TempGen{
-            sys: sysinfo::System::new(),
+            sample: None,
             name: "".to_string(),
         }
     }
 }
 
-#[async_trait]
-impl TimerGenerator for TempGen {
+#[async_trait(?Send)]
+impl SampleGenerator for TempGen {
+    type Sample = TempSample;
+
     async fn init(&mut self, arg: &GenArg) -> Result<()> {
-        self.sys.refresh_components_list();
-        let avail_comps = self.sys.get_components()
+        // NOTE: only used once to check that the component exists
+        let mut sys = sysinfo::System::new();
+        sys.refresh_components_list();
+        let avail_comps = sys.get_components()
             .into_iter()
             .map(|c| c.get_label())
             .collect::<HashSet<&str>>();
@@ -41,34 +49,43 @@ impl TimerGenerator for TempGen {
         Ok(())
     }
 
-    async fn update(&mut self) -> Result<()> {
-        self.sys.refresh_components();
+    fn update(&mut self, sample: Rc<TempSample>) -> Result<()> {
+        self.sample = Some(sample);
         Ok(())
     }
 
-    fn display(&self, _name: &str, arg: &GenArg) -> Result<String> {
-        let comp = self.sys.get_components()
-            .into_iter()
-            .find(|c| c.get_label() == self.name)
-            .ok_or(ExitReason::Error)?;
+    fn display(&self, _name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
+        let sample = match &self.sample {
+            Some(s) => s,
+            None => return Ok(Vec::new()),
+        };
 
-        let temp = comp.get_temperature().trunc();
+        let comp = sample.components
+            .iter()
+            .find(|c| c.label == self.name)
+            .ok_or_else(|| ExitReason::failed(format!("sensor {} is gone", self.name)))?;
+
+        let temp = comp.temperature.trunc();
         // let max = comp.get_max();
 
         let o = arg.get_builder()
             .add(temp.to_string())
             .add("°C")
             .color_step(temp as i32, LEVELS)
-            .to_string();
+            .into_markup();
 
         Ok(o)
     }
 
-    async fn on_msg(&mut self, _msg: String) -> Result<bool> {
-        Ok(false)
-    }
-
     fn get_delay(&self, arg: &GenArg) -> u64 {
         arg.timeout.unwrap_or(2)
     }
+
+    fn export_metrics(&mut self, m: &Metrics) {
+        let comp = self.sample.as_ref()
+            .and_then(|s| s.components.iter().find(|c| c.label == self.name));
+        if let Some(c) = comp {
+            m.set("temperature_celsius", "Component temperature", &[("component", &c.label)], c.temperature as f64);
+        }
+    }
 }
@@ -1,7 +1,8 @@
 use async_trait::async_trait;
 use chrono::prelude::*;
-use std::time::Instant;
+use std::time::{Duration,Instant,SystemTime,UNIX_EPOCH};
 use super::{TimerGenerator,GenArg,Result};
+use crate::dzen_format::markup::Markup;
 
 struct Timer {
     start: Instant,
@@ -11,6 +12,7 @@ struct Timer {
 pub struct TimeGen {
     datetime: DateTime<Local>,
     timer: Option<Timer>,
+    step: Duration,
 }
 
 impl TimeGen {
@@ -18,12 +20,20 @@ impl TimeGen {
         TimeGen{
             datetime: Local::now(),
             timer: None,
+            step: Duration::from_secs(60),
         }
     }
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 impl TimerGenerator for TimeGen {
+    async fn init(&mut self, arg: &GenArg) -> Result<()> {
+        if let Some(step) = arg.step {
+            self.step = Duration::from_secs(step);
+        }
+        Ok(())
+    }
+
     async fn update(&mut self) -> Result<()> {
         self.datetime = Local::now();
         if let Some(t) = &mut self.timer {
@@ -32,7 +42,7 @@ impl TimerGenerator for TimeGen {
         Ok(())
     }
 
-    fn display(&self, name: &str, arg: &GenArg) -> Result<String> {
+    fn display(&self, name: &str, arg: &GenArg) -> Result<Vec<Markup>> {
         let d = match self.datetime.weekday() {
             Weekday::Mon => "Mån",
             Weekday::Tue => "Tis",
@@ -59,8 +69,9 @@ impl TimerGenerator for TimeGen {
             s = s.add(self.datetime.format("%H:%M").to_string())
         }
 
-        Ok(s.name_click(1, name)
-           .to_string())
+        Ok(s.guard(self.timer.is_some(), |s| s.name_scroll(name))
+           .name_click(1, name)
+           .into_markup())
     }
 
     async fn on_msg(&mut self, msg: String) -> Result<bool> {
@@ -72,12 +83,52 @@ impl TimerGenerator for TimeGen {
                 self.timer = Some(Timer {start: n, now: n});
             }
             return Ok(true);
+        } else if msg == "scroll up" || msg == "scroll down" {
+            // move the start of the stopwatch, but never into the future
+            if let Some(t) = &mut self.timer {
+                let n = Instant::now();
+                t.start = if msg == "scroll up" {
+                    t.start.checked_sub(self.step).unwrap_or(t.start)
+                } else {
+                    std::cmp::min(t.start + self.step, n)
+                };
+                t.now = n;
+            }
+            return Ok(true);
         } else if msg == "update" {
             return Ok(true);
         }
         Ok(false)
     }
 
+    // the stopwatch is saved as the unix time it was started
+    fn save_state(&self) -> Option<String> {
+        let t = self.timer.as_ref()?;
+        let started = SystemTime::now().checked_sub(t.start.elapsed())?;
+        let secs = started.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        Some(format!("stopwatch {}", secs))
+    }
+
+    fn restore_state(&mut self, state: &str) {
+        let secs = match state.strip_prefix("stopwatch ").map(|s| s.parse::<u64>()) {
+            Some(Ok(s)) => s,
+            _ => {
+                log::warn!("invalid saved state '{}'", state);
+                return;
+            }
+        };
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap_or_default();
+        let n = Instant::now();
+        self.timer = Some(Timer {start: n.checked_sub(since).unwrap_or(n), now: n});
+    }
+
+    // the timer doesn't run while sleeping, so the minute is probably wrong
+    fn wants_resume(&self) -> bool {
+        true
+    }
+
     fn get_delay(&self, _arg: &GenArg) -> u64 {
         if self.timer.is_some() {
             1
@@ -0,0 +1,95 @@
+use std::io;
+use tokio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::select;
+use tokio::sync::oneshot;
+use crate::config::SOCKET_PATH;
+use crate::tasks::{ExitReason,Exits};
+use crate::tasks::stats::Stats;
+
+// what the running bar can tell about itself
+#[derive(Clone)]
+pub struct IpcState {
+    pub exits: Exits,
+    pub stats: Stats,
+}
+
+fn answer(state: &IpcState, line: &str) -> String {
+    let mut words = line.split_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let args: Vec<&str> = words.collect();
+    match cmd {
+        "exits" => {
+            let exits = state.exits.borrow();
+            if exits.is_empty() {
+                return "nothing has exited\n".to_string();
+            }
+            exits.iter().map(|e| format!("{}\n", e)).collect()
+        },
+        "verbosity" => crate::logging::verbosity_cmd(&args),
+        "stats" => state.stats.report(),
+        _ => format!("unknown command '{}'\n", cmd),
+    }
+}
+
+// one command per connection
+async fn client(state: IpcState, mut stream: UnixStream) {
+    let (r, mut w) = stream.split();
+    let cmd = match BufReader::new(r).lines().next_line().await {
+        Ok(Some(l)) => l,
+        Ok(None) => return,
+        Err(e) => {
+            log::debug!("couldn't read ipc command '{}'", e);
+            return;
+        }
+    };
+
+    let ans = answer(&state, cmd.trim());
+    if let Err(e) = w.write_all(ans.as_bytes()).await {
+        log::debug!("couldn't answer ipc command '{}'", e);
+    }
+}
+
+pub async fn ipc_server(state: IpcState, shutdown: oneshot::Receiver<()>) -> ExitReason {
+    // a previous run might have left it behind
+    match std::fs::remove_file(SOCKET_PATH) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            log::warn!("couldn't remove old socket at {} because '{}'", SOCKET_PATH, e);
+        },
+        _ => (),
+    }
+
+    let mut listener = match UnixListener::bind(SOCKET_PATH) {
+        Ok(l) => l,
+        Err(e) => {
+            log::warn!("couldn't listen on {} because '{}'", SOCKET_PATH, e);
+            return ExitReason::NonFatal;
+        }
+    };
+
+    let main_loop = async {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::task::spawn_local(client(state.clone(), stream));
+                },
+                Err(e) => {
+                    log::warn!("couldn't accept ipc connection '{}'", e);
+                    break ExitReason::NonFatal;
+                }
+            }
+        }
+    };
+
+    let reason = select! {
+        r = main_loop => r,
+        _ = shutdown => ExitReason::Normal
+    };
+
+    if let Err(e) = std::fs::remove_file(SOCKET_PATH) {
+        log::warn!("couldn't remove socket at {} because '{}'", SOCKET_PATH, e);
+    }
+
+    reason
+}
@@ -0,0 +1,166 @@
+use dbus::message::{MatchRule,Message};
+use dbus_tokio::connection;
+use futures::stream::StreamExt;
+use tokio;
+use tokio::select;
+use tokio::sync::{mpsc,oneshot};
+use crate::tasks::ExitReason;
+
+// https://www.freedesktop.org/wiki/Software/systemd/logind/
+
+const MANAGER_IF: &str = "org.freedesktop.login1.Manager";
+const MANAGER_OBJ: &str = "/org/freedesktop/login1";
+
+// Handles one PrepareForSleep signal, sending `resume` to every
+// generator in `gens` after waking up. Fails if pipo is gone.
+async fn on_signal(msg: &Message, gens: &[String], to_pipo: &mut mpsc::Sender<String>) -> Result<(), ()> {
+    // true before sleeping and false after waking up
+    let sleeping: bool = match msg.get1() {
+        Some(b) => b,
+        None => {
+            log::warn!("PrepareForSleep didn't say if we are sleeping");
+            return Ok(());
+        }
+    };
+
+    if sleeping {
+        log::info!("going to sleep");
+        return Ok(());
+    }
+
+    log::info!("woke up, refreshing everything");
+    for g in gens.iter() {
+        to_pipo.send(format!("{} resume", g)).await.map_err(|_| ())?;
+    }
+    Ok(())
+}
+
+// Sends `resume` to every generator in `gens` when the computer wakes
+// up from suspend or hibernation.
+//
+// NOTE: the bus is found the usual way, so DBUS_SYSTEM_BUS_ADDRESS can
+// point this to a private bus with a fake logind.
+pub async fn sleep_watcher(
+    gens: Vec<String>,
+    mut to_pipo: mpsc::Sender<String>,
+    shutdown: oneshot::Receiver<()>
+) -> ExitReason
+{
+    let (resource, conn) = match connection::new_system_sync() {
+        Ok(rc) => rc,
+        Err(e) => {
+            log::warn!("couldn't connect to the system bus, won't notice resumes. '{}'", e);
+            return ExitReason::NonFatal;
+        }
+    };
+
+    let main_loop = async {
+        let mut rule = MatchRule::new_signal(MANAGER_IF, "PrepareForSleep");
+        rule.path = Some(MANAGER_OBJ.into());
+        let (mm, mut stream) = conn.add_match(rule).await?.msg_stream();
+
+        while let Some(msg) = stream.next().await {
+            if on_signal(&msg, &gens, &mut to_pipo).await.is_err() {
+                break;
+            }
+        }
+
+        conn.remove_match(mm.token()).await?;
+        Ok::<(), dbus::Error>(())
+    };
+
+    select! {
+        err = resource => {
+            log::warn!("lost connection to the system bus. '{}'", err);
+            ExitReason::NonFatal
+        },
+        res = main_loop => match res {
+            Ok(()) => ExitReason::NonFatal,
+            Err(e) => {
+                log::warn!("couldn't listen for sleep signals. '{}'", e);
+                ExitReason::NonFatal
+            }
+        },
+        _ = shutdown => ExitReason::Normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbus::channel::{BusType, Channel};
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::time::Duration;
+    use tokio::task::spawn_local;
+    use tokio::time::{delay_for, timeout};
+    use crate::tasks::run_local;
+
+    // a private bus for the fake logind, gone when dropped
+    struct Bus(Child);
+
+    impl Bus {
+        fn start() -> (Self, String) {
+            let mut child = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("couldn't start dbus-daemon");
+            let mut addr = String::new();
+            BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut addr).unwrap();
+            (Bus(child), addr.trim().to_string())
+        }
+    }
+
+    impl std::ops::Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn prepare_for_sleep(conn: &Channel, sleeping: bool) {
+        let msg = Message::new_signal(MANAGER_OBJ, MANAGER_IF, "PrepareForSleep")
+            .unwrap()
+            .append1(sleeping);
+        conn.send(msg).unwrap();
+        conn.flush();
+    }
+
+    #[test]
+    fn resumes_reach_pipo() {
+        let (_bus, addr) = Bus::start();
+        std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &addr);
+        let mut logind = Channel::get_private(BusType::System).unwrap();
+        logind.register().unwrap();
+
+        run_local(async {
+            let (to_pipo, mut internal) = mpsc::channel(8);
+            let (stop, shutdown) = oneshot::channel();
+            let gens = vec!["NET-0".to_string(), "wifi".to_string()];
+            let watcher = spawn_local(sleep_watcher(gens, to_pipo, shutdown));
+            // until the watcher has added its match
+            delay_for(Duration::from_millis(300)).await;
+
+            prepare_for_sleep(&logind, true);
+            prepare_for_sleep(&logind, false);
+
+            let mut lines = Vec::new();
+            for _ in 0..2 {
+                let l = timeout(Duration::from_secs(5), internal.recv()).await
+                    .expect("no resume line")
+                    .unwrap();
+                lines.push(l);
+            }
+            assert_eq!(lines, vec!["NET-0 resume", "wifi resume"]);
+
+            // going to sleep isn't forwarded
+            delay_for(Duration::from_millis(100)).await;
+            assert!(internal.try_recv().is_err());
+
+            stop.send(()).unwrap();
+            assert!(watcher.await.unwrap().is_normal());
+        });
+    }
+}
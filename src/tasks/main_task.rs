@@ -4,68 +4,236 @@ use std::collections::HashMap;
 use tokio;
 use tokio::sync::mpsc;
 use tokio::sync::broadcast;
-use tokio::task::JoinHandle;
+use tokio::task::{JoinHandle,spawn_local};
 use tokio::sync::oneshot;
+use tokio::select;
+use tokio::time;
 use crate::bar::*;
-use super::{ProcessExitReason,ExitReason};
-use super::generator::{genid_to_generator,GenArg};
-use super::dzen::dzen_printer;
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::{ProcessExitReason,Exit,Exits,Kind,labeled};
+use super::generator::{genid_to_generator,GenArg,Shared,Output};
+use super::printer::printer;
 use super::pipo::pipo_reader;
+use super::uevent::{uevent_listener,Subscribers};
+use super::logind::sleep_watcher;
+use super::ipc::{ipc_server,IpcState};
+use super::sampler::Sampler;
+use super::state::{StateStore,state_writer};
+use super::ready::Readiness;
+use super::stats::Stats;
+use super::metrics::{Metrics,metrics_server};
+use crate::systemd;
+use crate::config::{SAVE_STATE,METRICS_ADDR};
 
 const MPSC_SIZE: usize = 32;
+// how long generators get to start before we tell systemd we're ready
+// anyway, so one slow `init` doesn't fail the whole unit
+const READY_TIMEOUT: time::Duration = time::Duration::from_secs(10);
 
 pub async fn main(setup: SetupConfig) -> ProcessExitReason {
-    let mut tasks = FuturesUnordered::<JoinHandle<ExitReason>>::new();
+    let mut tasks = FuturesUnordered::<JoinHandle<Exit>>::new();
+    let exits: Exits = Rc::new(RefCell::new(Vec::new()));
+    let readiness = Readiness::new();
 
     let mut pipo_map = HashMap::new();
+    let mut uevent_subs = Subscribers::new();
+    let mut resume_subs = Vec::new();
+    let state = if SAVE_STATE {StateStore::load()} else {StateStore::disabled()};
+    let stats = Stats::new();
+    let metrics = Metrics::new();
+    let shared = Shared {
+        sampler: Sampler::new(stats.clone()),
+        state: state.clone(),
+        stats: stats.clone(),
+        metrics: metrics.clone(),
+    };
 
-    let mut shutdown_pipo = {
-        let (broad_send, _) = broadcast::channel(MPSC_SIZE);
+    let mut shutdown = {
+        let (control_send, _) = broadcast::channel(MPSC_SIZE);
+        let mut outputs = HashMap::new();
+        let (internal_send, internal_recv) = mpsc::channel(MPSC_SIZE);
+        let mut shutdown = Vec::new();
 
         for g in setup.iter() {
             let (pipo_send, pipo_recv) = mpsc::channel(MPSC_SIZE);
-            let bs = broad_send.clone();
+            let (output, latest) = Output::channel(readiness.token());
             let a = setup.get_arg(g).cloned().unwrap_or(GenArg::empty());
             let gg = *g;
             let name = setup.get_name(*g).cloned().unwrap_or(g.to_string());
+            outputs.insert(*g, (name.clone(), latest));
             let name2 = name.clone();
-            tasks.push(tokio::spawn(async move {
-                let mut gen = genid_to_generator(gg);
-                gen.start(bs, pipo_recv, gg, a, name2).await
-            }));
+            let mut gen = genid_to_generator(gg, &shared);
+            for sub in gen.uevent_subsystems() {
+                uevent_subs.entry(sub).or_default().push(name.clone());
+            }
+            if gen.wants_resume(&a) {
+                resume_subs.push(name.clone());
+            }
+            tasks.push(spawn_local(labeled(Kind::Generator, format!("generator '{}'", name), async move {
+                gen.start(output, pipo_recv, a, name2).await
+            })));
             if let Some(_) = pipo_map.insert(name, pipo_send) {
                 panic!("some generators have the same name!");
             }
         }
 
         for b in setup.bars() {
-            tasks.push(tokio::spawn(dzen_printer(broad_send.subscribe(), b.clone())));
+            let gens = b.iter()
+                .map(|id| (*id, outputs[id].clone()))
+                .collect();
+            let label = format!("bar on {}", b.get_output());
+            tasks.push(spawn_local(labeled(Kind::Bar, label, printer(gens, control_send.subscribe(), b.clone(), readiness.token(), stats.clone(), internal_send.clone()))));
+        }
+
+        if !uevent_subs.is_empty() {
+            let (sp, uevent_shutdown_recv) = oneshot::channel();
+            let listener = uevent_listener(uevent_subs, internal_send.clone(), uevent_shutdown_recv);
+            tasks.push(spawn_local(labeled(Kind::Service, "uevent listener".to_string(), listener)));
+            shutdown.push(sp);
         }
 
-        Some({
-            let (sp, pipo_shutdown_recv) = oneshot::channel();
-            tasks.push(tokio::spawn(pipo_reader(pipo_map, pipo_shutdown_recv, broad_send)));
-            sp
-        })
+        let (sp, sleep_shutdown_recv) = oneshot::channel();
+        let watcher = sleep_watcher(resume_subs, internal_send, sleep_shutdown_recv);
+        tasks.push(spawn_local(labeled(Kind::Service, "sleep watcher".to_string(), watcher)));
+        shutdown.push(sp);
+
+        if state.is_enabled() {
+            let (sp, state_shutdown_recv) = oneshot::channel();
+            let writer = state_writer(state.clone(), state_shutdown_recv);
+            tasks.push(spawn_local(labeled(Kind::Service, "state writer".to_string(), writer)));
+            shutdown.push(sp);
+        }
+
+        let (sp, ipc_shutdown_recv) = oneshot::channel();
+        let ipc = IpcState {
+            exits: exits.clone(),
+            stats: stats.clone(),
+        };
+        tasks.push(spawn_local(labeled(Kind::Service, "ipc server".to_string(), ipc_server(ipc, ipc_shutdown_recv))));
+        shutdown.push(sp);
+
+        if let Some(addr) = METRICS_ADDR {
+            let (sp, metrics_shutdown_recv) = oneshot::channel();
+            let server = metrics_server(metrics, addr, metrics_shutdown_recv);
+            tasks.push(spawn_local(labeled(Kind::Service, "metrics server".to_string(), server)));
+            shutdown.push(sp);
+        }
+
+        let (sp, pipo_shutdown_recv) = oneshot::channel();
+        let recording = setup.bars().iter().any(|b| b.get_record().is_some());
+        let reader = pipo_reader(pipo_map, pipo_shutdown_recv, control_send, internal_recv, recording, stats.clone());
+        tasks.push(spawn_local(labeled(Kind::Service, "pipo reader".to_string(), reader)));
+        shutdown.push(sp);
+
+        shutdown
     };
 
+    // systemd restarts us if these stop, like when some task blocks
+    // the thread
+    let watchdog = systemd::watchdog_interval();
+    let mut watchdog_timer = time::interval(watchdog.map_or(time::Duration::from_secs(3600), |w| w / 2));
+    let mut ready = false;
+    let ready_wait = time::timeout(READY_TIMEOUT, readiness.wait());
+    tokio::pin!(ready_wait);
+
     let mut reason = ProcessExitReason::new();
-    while let Some(res_r) = tasks.next().await {
+    loop {
+        let res_r = select! {
+            r = tasks.next() => match r {
+                Some(r) => r,
+                None => break,
+            },
+            r = &mut ready_wait, if !ready => {
+                if r.is_err() {
+                    log::warn!("still not ready after {:?}, telling systemd anyway", READY_TIMEOUT);
+                }
+                ready = true;
+                systemd::notify("READY=1");
+                systemd::notify(&status(&exits));
+                continue;
+            },
+            _ = watchdog_timer.tick(), if watchdog.is_some() => {
+                systemd::notify("WATCHDOG=1");
+                continue;
+            }
+        };
+
         if let Err(e) = res_r {
             log::warn!("coudln't join??, '{}'", e);
             continue;
         }
 
-        let er = res_r.unwrap();
-        if er == ExitReason::NonFatal {
-            log::warn!("something exited non-fatally!");
+        let exit = res_r.unwrap();
+        let non_fatal = exit.reason.is_non_fatal();
+        if non_fatal {
+            log::warn!("{} exited non-fatally!", exit.component);
+        } else if !exit.reason.is_normal() {
+            log::error!("{} exited because of {}", exit.component, exit.reason);
+        }
+        reason = reason.combine(&exit);
+        exits.borrow_mut().push(exit);
+        if ready {
+            systemd::notify(&status(&exits));
+        }
+        if non_fatal {
             continue;
         }
 
-        shutdown_pipo = shutdown_pipo.and_then(|p| p.send(()).ok()).and(None);
-        reason = reason.combine(er);
+        if !shutdown.is_empty() {
+            systemd::notify("STOPPING=1");
+        }
+        for sp in shutdown.drain(..) {
+            let _ = sp.send(());
+        }
+    }
+
+    // summarise everything that didn't go as planned
+    for e in exits.borrow().iter().filter(|e| !e.reason.is_normal()) {
+        log::warn!("{}", e);
+    }
+
+    // every generator has saved its final state by now
+    if let Err(e) = state.write() {
+        log::warn!("couldn't save state because '{}'", e);
     }
 
     log::info!("all tasks have finished, exiting...");
     reason
 }
+
+// `STATUS=` for systemd, which generators have failed
+pub fn status(exits: &Exits) -> String {
+    let failed: Vec<String> = exits.borrow()
+        .iter()
+        .filter(|e| !e.reason.is_normal() && e.kind == Kind::Generator)
+        .map(|e| e.component.clone())
+        .collect();
+
+    if failed.is_empty() {
+        "STATUS=running".to_string()
+    } else {
+        format!("STATUS=running, failed: {}", failed.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use crate::tasks::ExitReason;
+
+    fn exit(kind: Kind, component: &str, reason: ExitReason) -> Exit {
+        Exit {kind, component: component.to_string(), reason, errors: Vec::new(), time: Local::now()}
+    }
+
+    #[test]
+    fn status_goes_by_kind_not_label() {
+        let exits: Exits = Rc::new(RefCell::new(vec![
+            exit(Kind::Generator, "cpu", ExitReason::NonFatal),
+            exit(Kind::Service, "generator of uevents", ExitReason::failed("gone")),
+            exit(Kind::Generator, "ram", ExitReason::Normal),
+        ]));
+        assert_eq!(status(&exits), "STATUS=running, failed: cpu");
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use tokio;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::select;
+use tokio::sync::oneshot;
+use crate::tasks::ExitReason;
+
+const PREFIX: &str = "statusbar_";
+
+struct Family {
+    help: &'static str,
+    // the labels rendered as `{a="b"}` mapped to the value
+    values: BTreeMap<String, f64>,
+}
+
+// The latest values generators have computed, as Prometheus gauges.
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Rc<RefCell<BTreeMap<&'static str, Family>>>,
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let inner: Vec<String> = labels.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            inner: Rc::new(RefCell::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn set(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+        let labels = render_labels(labels);
+        self.inner.borrow_mut()
+            .entry(name)
+            .or_insert_with(|| Family {help, values: BTreeMap::new()})
+            .values
+            .insert(labels, value);
+    }
+
+    // for things that are gone, like an unplugged disk
+    pub fn remove(&self, name: &'static str, labels: &[(&str, &str)]) {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(fam) = inner.get_mut(name) {
+            fam.values.remove(&render_labels(labels));
+            if fam.values.is_empty() {
+                inner.remove(name);
+            }
+        }
+    }
+
+    // https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        for (name, fam) in self.inner.borrow().iter() {
+            let _ = writeln!(s, "# HELP {}{} {}", PREFIX, name, fam.help);
+            let _ = writeln!(s, "# TYPE {}{} gauge", PREFIX, name);
+            for (labels, v) in fam.values.iter() {
+                let _ = writeln!(s, "{}{}{} {}", PREFIX, name, labels, v);
+            }
+        }
+        s
+    }
+}
+
+// Answers every request with the metrics, whatever was asked for.
+async fn serve<S>(metrics: Metrics, stream: S)
+where S: AsyncRead + AsyncWrite + Unpin
+{
+    let mut stream = BufReader::new(stream);
+    // skip the request, which ends with an empty line
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match stream.read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) if line.trim_end().is_empty() => break,
+            Ok(_) => (),
+            Err(e) => {
+                log::debug!("couldn't read metrics request '{}'", e);
+                return;
+            }
+        }
+    }
+
+    let body = metrics.render();
+    let resp = format!("HTTP/1.1 200 OK\r\n\
+                        Content-Type: text/plain; version=0.0.4\r\n\
+                        Content-Length: {}\r\n\
+                        Connection: close\r\n\r\n{}", body.len(), body);
+    if let Err(e) = stream.get_mut().write_all(resp.as_bytes()).await {
+        log::debug!("couldn't send metrics '{}'", e);
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, String),
+}
+
+impl Listener {
+    // `unix:<path>` or `<ip>:<port>`
+    async fn bind(addr: &str) -> io::Result<Self> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+            Ok(Listener::Unix(UnixListener::bind(path)?, path.to_string()))
+        } else {
+            let a: SocketAddr = addr.parse().map_err(io::Error::other)?;
+            Ok(Listener::Tcp(TcpListener::bind(a).await?))
+        }
+    }
+
+    async fn accept_and_serve(&mut self, metrics: &Metrics) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => {
+                let (s, _) = l.accept().await?;
+                tokio::task::spawn_local(serve(metrics.clone(), s));
+            },
+            Listener::Unix(l, _) => {
+                let (s, _) = l.accept().await?;
+                tokio::task::spawn_local(serve(metrics.clone(), s));
+            },
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::warn!("couldn't remove socket at {} because '{}'", path, e);
+            }
+        }
+    }
+}
+
+pub async fn metrics_server(metrics: Metrics, addr: &'static str, shutdown: oneshot::Receiver<()>) -> ExitReason {
+    let mut listener = match Listener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            log::warn!("couldn't serve metrics on {} because '{}'", addr, e);
+            return ExitReason::NonFatal;
+        }
+    };
+
+    let main_loop = async {
+        loop {
+            if let Err(e) = listener.accept_and_serve(&metrics).await {
+                log::warn!("couldn't accept metrics connection '{}'", e);
+                break ExitReason::NonFatal;
+            }
+        }
+    };
+
+    select! {
+        r = main_loop => r,
+        _ = shutdown => ExitReason::Normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_series_are_not_rendered() {
+        let m = Metrics::new();
+        m.set("disk_total_bytes", "Size", &[("mount", "/")], 1.0);
+        m.set("disk_total_bytes", "Size", &[("mount", "/mnt")], 2.0);
+        m.remove("disk_total_bytes", &[("mount", "/mnt")]);
+        assert_eq!(m.render(), "# HELP statusbar_disk_total_bytes Size\n\
+                                # TYPE statusbar_disk_total_bytes gauge\n\
+                                statusbar_disk_total_bytes{mount=\"/\"} 1\n");
+
+        // no empty families either
+        m.remove("disk_total_bytes", &[("mount", "/")]);
+        assert_eq!(m.render(), "");
+    }
+}
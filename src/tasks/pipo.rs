@@ -11,21 +11,25 @@ use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::signal::unix::{signal, SignalKind};
 use crate::config::*;
 use crate::tasks::{ExitReason,Msg};
+use crate::tasks::stats::Stats;
 
+// `internal` receives lines in the same format as the pipe, but from
+// other tasks. The printers only get every line if some bar is
+// `recording` them.
 pub async fn pipo_reader(
     mut gens: HashMap<String, mpsc::Sender<String>>,
     shutdown: oneshot::Receiver<()>,
-    to_printer: broadcast::Sender<Msg>
+    to_printer: broadcast::Sender<Msg>,
+    mut internal: mpsc::Receiver<String>,
+    recording: bool,
+    stats: Stats
 ) -> ExitReason
 {
     // create pipe
     match mkfifo(FIFO_PATH, stat::Mode::S_IRWXU) {
         Ok(()) => (),
         Err(Sys(errno)) if errno == EEXIST => (),
-        Err(e) => {
-            log::error!("couldn't create pipo at {} because '{}'", FIFO_PATH, e);
-            return ExitReason::Error;
-        }
+        Err(e) => return ExitReason::error(format!("couldn't create pipo at {}", FIFO_PATH), e),
     };
 
     let mut int_stream = signal(SignalKind::interrupt()).unwrap();
@@ -41,24 +45,31 @@ pub async fn pipo_reader(
             .await;
 
         let mut reader = match file {
-            Ok(f) => BufReader::new(f),
-            Err(e) => {
-                log::error!("couldn't open pipe '{}' because '{}'", FIFO_PATH, e);
-                return ExitReason::Error;
-            }
+            Ok(f) => BufReader::new(f).lines(),
+            Err(e) => return ExitReason::error(format!("couldn't open pipe '{}'", FIFO_PATH), e),
         };
 
         let mut er = ExitReason::Normal;
-        let mut buf = String::new();
-        while let Ok(c) = reader.read_line(&mut buf).await {
-            if c == 0 {
-                break;
-            }
-
-            if !buf.ends_with("\n") {
-                log::warn!("read line without a newline");
+        let mut internal_open = true;
+        loop {
+            let line = select! {
+                l = reader.next_line() => match l {
+                    Ok(Some(l)) => l,
+                    _ => break,
+                },
+                l = internal.recv(), if internal_open => match l {
+                    Some(l) => l,
+                    None => {
+                        internal_open = false;
+                        continue;
+                    }
+                }
+            };
+            let content = line.trim_end();
+            if recording {
+                // the printers might all be gone already, which is fine
+                let _ = to_printer.send(Msg::Pipo(content.to_string()));
             }
-            let content = buf.trim_end();
 
             let (gid, msg) =
                 match content.match_indices(" ").next() {
@@ -76,16 +87,13 @@ pub async fn pipo_reader(
                     Err(mpsc::error::TrySendError::Closed(_)) => {
                         log::warn!("some generator receiver closed");
                     },
-                    _ => ()
-                }
-            } else if gid == "TRAY" {
-                if let Err(e) = to_printer.send(Msg::Tray) {
-                    log::error!("to printer died? '{:?}'", e);
-                    er = ExitReason::Error;
-                    break;
+                    Err(mpsc::error::TrySendError::Full(_)) => stats.dropped(gid),
+                    Ok(()) => stats.message(gid),
                 }
+            } else if gid == "TRAY" && to_printer.send(Msg::Tray).is_err() {
+                er = ExitReason::failed("every printer has died");
+                break;
             }
-            buf.clear();
         }
 
         er
@@ -95,7 +103,7 @@ pub async fn pipo_reader(
         _ = int_stream.recv() => ExitReason::Signal,
         _ = term_stream.recv() => ExitReason::Signal,
         r = main_loop => r,
-        _ = shutdown => ExitReason::Error
+        _ = shutdown => ExitReason::failed("another task stopped")
     };
 
     // remove pipe
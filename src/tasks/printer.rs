@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use tokio;
+use tokio::sync::broadcast::{self, RecvError};
+use std::rc::Rc;
+use tokio::sync::{mpsc, Notify};
+use tokio::select;
+use futures::stream::{select_all, StreamExt};
+use tokio::time::{self, Duration, Instant};
+use crate::bar::*;
+use crate::tasks::ExitReason;
+use crate::tasks::generator::{GenId,Latest};
+use crate::tasks::ready::ReadyToken;
+use crate::tasks::stats::Stats;
+use crate::tasks::backend::{bar_to_backend,fit,Line,Segment};
+use crate::dzen_format::markup::{self, Markup};
+use super::Msg;
+
+const ACC_DUR: Duration = Duration::from_millis(40);
+
+async fn notified(n: &Option<Rc<Notify>>) {
+    match n {
+        Some(n) => n.notified().await,
+        None => futures::future::pending().await,
+    }
+}
+
+fn segments<'a>(
+    it: impl Iterator<Item = &'a GenId>,
+    output: &'a HashMap<GenId, Vec<Markup>>,
+    names: &'a HashMap<GenId, String>,
+    config: &BarConfig
+) -> Vec<Segment<'a>>
+{
+    it.map(|x| Segment {
+        name: names.get(x).unwrap().as_str(),
+        text: output.get(x).unwrap().as_slice().into(),
+        priority: config.get_priority(*x),
+    })
+        .filter(|s| !s.text.is_empty())
+        .collect()
+}
+
+// `gens` has the name and output of every generator on this bar
+pub async fn printer(
+    gens: HashMap<GenId, (String, Latest)>,
+    mut control: broadcast::Receiver<Msg>,
+    config: BarConfig,
+    mut ready: ReadyToken,
+    stats: Stats,
+    to_pipo: mpsc::Sender<String>
+) -> ExitReason
+{
+    let bar = config.get_output().to_string();
+
+    // aliases
+    let sep = markup::parse(config.get_separator());
+    let pad = config.get_padding();
+
+    let mut names = HashMap::new();
+    let mut latest = Vec::new();
+    for (id, (name, l)) in gens.into_iter() {
+        names.insert(id, name);
+        latest.push((id, l));
+    }
+
+    // output buffer
+    let mut output = HashMap::<GenId, Vec<Markup>>::new();
+    for id in config.iter() {
+        output.insert(*id, vec![Markup::Text("xxx".to_string())]);
+    }
+
+    let mut backend = bar_to_backend(&config, &stats);
+    if let Err(e) = backend.spawn().await {
+        backend.teardown().await;
+        return ExitReason::error(format!("couldn't spawn {:?}", config.get_backend()), e);
+    }
+    backend.handle_clicks(to_pipo);
+    ready.ready();
+
+    let tray = config.wants_tray() && backend.supports_tray();
+    if config.wants_tray() && !tray {
+        log::warn!("{:?} can't show a tray", config.get_backend());
+    }
+    if tray {
+        backend.restart_tray(2);
+    }
+
+    // every generator of this bar as one stream, which only ends when
+    // all of them have
+    let mut updates = select_all(latest.into_iter().map(|(id, l)| l.map(move |s| (id, s))));
+    let mut updates_open = true;
+    let mut control_open = true;
+
+    let relayout = backend.layout_changed();
+    let mut delay = time::delay_for(ACC_DUR);
+    let mut waiting = false;
+    // receive new strings to output buffer and occasionally print
+    // them to the backend
+    let reason = loop {
+        // accumulate close changes as one (`ACC_DUR` time from first message)
+        select! {
+            _    = &mut delay, if waiting => (),
+            // shrink again for the new room
+            _    = notified(&relayout) => (),
+            up = updates.next(), if updates_open =>
+                match up {
+                    // keep showing the last outputs
+                    None if control_open => {
+                        updates_open = false;
+                        continue;
+                    },
+                    None => break ExitReason::Normal,
+                    Some((_, None)) => continue,
+                    Some((id, Some(msg))) => {
+                        if let Some(old) = output.get_mut(&id) {
+                            if *old == msg {
+                                continue;
+                            }
+                            *old = msg;
+
+                            if !waiting {
+                                delay.reset(Instant::now() + ACC_DUR);
+                                waiting = true;
+                            }
+                        }
+                        continue;
+                    }
+                },
+            ctl = control.recv(), if control_open =>
+                match ctl {
+                    Ok(Msg::Pipo(l)) => {
+                        backend.control(&format!("pipo {}", l));
+                        continue;
+                    },
+                    Ok(Msg::Tray) => {
+                        backend.control("tray");
+                        if tray {
+                            backend.restart_tray(0);
+                        }
+                    },
+                    Err(RecvError::Lagged(n)) => {
+                        stats.lagged(&bar, n);
+                        backend.control(&format!("lagged {}", n));
+                        continue;
+                    },
+                    Err(_) if updates_open => {
+                        control_open = false;
+                        continue;
+                    },
+                    Err(_) => break ExitReason::Normal
+                }
+        }
+        waiting = false;
+
+        let mut line = Line {
+            left: segments(config.iter_left(), &output, &names, &config),
+            right: segments(config.iter_right(), &output, &names, &config),
+            separator: &sep,
+            padding: pad,
+        };
+        if let Some(f) = backend.fit() {
+            fit::shrink(&mut line, f);
+        }
+
+        match backend.render(&line).await {
+            Ok(true) => stats.redraw(&bar),
+            Ok(false) => (),
+            Err(e) => break ExitReason::error(format!("couldn't write to {:?}", config.get_backend()), e),
+        }
+    };
+
+    backend.teardown().await;
+    reason
+}
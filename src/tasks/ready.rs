@@ -0,0 +1,52 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use tokio::sync::Notify;
+
+// Counts down the tasks that haven't started properly yet.
+#[derive(Clone)]
+pub struct Readiness {
+    left: Rc<Cell<usize>>,
+    notify: Rc<Notify>,
+}
+
+// Held by one task, which is ready when this is used or dropped. A
+// task that gives up early shouldn't keep everyone waiting.
+pub struct ReadyToken(Option<Readiness>);
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness {
+            left: Rc::new(Cell::new(0)),
+            notify: Rc::new(Notify::new()),
+        }
+    }
+
+    pub fn token(&self) -> ReadyToken {
+        self.left.set(self.left.get() + 1);
+        ReadyToken(Some(self.clone()))
+    }
+
+    // resolves when every token has been used
+    pub async fn wait(&self) {
+        while self.left.get() > 0 {
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl ReadyToken {
+    pub fn ready(&mut self) {
+        if let Some(r) = self.0.take() {
+            r.left.set(r.left.get() - 1);
+            if r.left.get() == 0 {
+                r.notify.notify();
+            }
+        }
+    }
+}
+
+impl std::ops::Drop for ReadyToken {
+    fn drop(&mut self) {
+        self.ready();
+    }
+}
@@ -0,0 +1,377 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::cell::{Cell,RefCell};
+use std::rc::{Rc,Weak};
+use std::time::{Duration,Instant};
+use sysinfo::{System,SystemExt,ProcessorExt,NetworkExt,DiskExt,ComponentExt};
+use tokio;
+use tokio::select;
+use tokio::sync::{watch,Notify};
+use tokio::time::{delay_for,timeout};
+use crate::tasks::stats::Stats;
+
+#[derive(Clone,Copy,PartialEq,Eq,Hash,Debug)]
+pub enum Category {
+    Cpu,
+    Memory,
+    Networks,
+    Disks,
+    Components,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Cpu => "cpu",
+            Category::Memory => "memory",
+            Category::Networks => "networks",
+            Category::Disks => "disks",
+            Category::Components => "components",
+        }
+    }
+}
+
+// A snapshot of one sysinfo category. Every category is refreshed by
+// one task with its own `System`, no matter how many generators use it.
+// Refreshing reads a lot of files, so it happens on the blocking pool.
+pub trait Sample: Sized + Send + 'static {
+    const CATEGORY: Category;
+    fn init(_sys: &mut System) {}
+    fn take(sys: &mut System, elapsed: Duration) -> Self;
+}
+
+// `None` until the first sample, and again while a refresh is taking
+// longer than its deadline
+pub type Latest<S> = watch::Receiver<Option<Rc<S>>>;
+
+#[derive(Debug)]
+pub struct CpuSample {
+    pub global: f32,
+    pub cores: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub struct MemSample {
+    pub used: u64,
+    pub total: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
+}
+
+#[derive(Debug)]
+pub struct NetData {
+    pub name: String,
+    pub transmitted: u64,
+    pub received: u64,
+    pub total_transmitted: u64,
+    pub total_received: u64,
+}
+
+#[derive(Debug)]
+pub struct NetSample {
+    // time since the previous sample, `transmitted` and `received`
+    // are over this duration
+    pub elapsed: Duration,
+    pub interfaces: Vec<NetData>,
+}
+
+#[derive(Debug)]
+pub struct DiskData {
+    pub mount_point: PathBuf,
+    pub file_system: String,
+    pub total: u64,
+    pub available: u64,
+}
+
+#[derive(Debug)]
+pub struct DiskSample {
+    pub disks: Vec<DiskData>,
+}
+
+#[derive(Debug)]
+pub struct TempData {
+    pub label: String,
+    pub temperature: f32,
+}
+
+#[derive(Debug)]
+pub struct TempSample {
+    pub components: Vec<TempData>,
+}
+
+impl Sample for CpuSample {
+    const CATEGORY: Category = Category::Cpu;
+
+    fn take(sys: &mut System, _elapsed: Duration) -> Self {
+        sys.refresh_cpu();
+        CpuSample {
+            global: sys.get_global_processor_info().get_cpu_usage(),
+            cores: sys.get_processors().iter().map(|p| p.get_cpu_usage()).collect(),
+        }
+    }
+}
+
+impl Sample for MemSample {
+    const CATEGORY: Category = Category::Memory;
+
+    fn take(sys: &mut System, _elapsed: Duration) -> Self {
+        sys.refresh_memory();
+        MemSample {
+            used: sys.get_used_memory(),
+            total: sys.get_total_memory(),
+            used_swap: sys.get_used_swap(),
+            total_swap: sys.get_total_swap(),
+        }
+    }
+}
+
+impl Sample for NetSample {
+    const CATEGORY: Category = Category::Networks;
+
+    fn init(sys: &mut System) {
+        sys.refresh_networks_list();
+    }
+
+    fn take(sys: &mut System, elapsed: Duration) -> Self {
+        // NOTE: this also refreshes the data of already known
+        // interfaces, so new interfaces show up without an extra refresh
+        sys.refresh_networks_list();
+        NetSample {
+            elapsed,
+            interfaces: sys.get_networks()
+                .into_iter()
+                .map(|(name, n)| NetData {
+                    name: name.to_string(),
+                    transmitted: n.get_transmitted(),
+                    received: n.get_received(),
+                    total_transmitted: n.get_total_transmitted(),
+                    total_received: n.get_total_received(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Sample for DiskSample {
+    const CATEGORY: Category = Category::Disks;
+
+    fn take(sys: &mut System, _elapsed: Duration) -> Self {
+        sys.refresh_disks_list();
+        sys.refresh_disks();
+        DiskSample {
+            disks: sys.get_disks()
+                .iter()
+                .map(|d| DiskData {
+                    mount_point: d.get_mount_point().to_path_buf(),
+                    file_system: String::from_utf8_lossy(d.get_file_system()).into_owned(),
+                    total: d.get_total_space(),
+                    available: d.get_available_space(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Sample for TempSample {
+    const CATEGORY: Category = Category::Components;
+
+    fn init(sys: &mut System) {
+        sys.refresh_components_list();
+    }
+
+    fn take(sys: &mut System, _elapsed: Duration) -> Self {
+        sys.refresh_components();
+        TempSample {
+            components: sys.get_components()
+                .iter()
+                .map(|c| TempData {
+                    label: c.get_label().to_string(),
+                    temperature: c.get_temperature(),
+                })
+                .collect(),
+        }
+    }
+}
+
+struct Entry {
+    // a `Latest<S>` for the `S` of this category
+    latest: Box<dyn Any>,
+    period: Rc<Cell<u64>>,
+    deadline: Rc<Cell<u64>>,
+    wake: Rc<Notify>,
+}
+
+type Entries = RefCell<HashMap<Category, Entry>>;
+
+#[derive(Clone)]
+pub struct Sampler {
+    inner: Rc<Entries>,
+    stats: Stats,
+}
+
+impl Sampler {
+    pub fn new(stats: Stats) -> Self {
+        Sampler {
+            inner: Rc::new(RefCell::new(HashMap::new())),
+            stats,
+        }
+    }
+
+    // Starts sampling `S` if no one else has. The category is sampled
+    // at the shortest period and with the shortest deadline anyone has
+    // asked for.
+    pub fn subscribe<S: Sample>(&self, period: Duration, deadline: Duration) -> Latest<S> {
+        let millis = period.as_millis() as u64;
+        let deadline_millis = deadline.as_millis() as u64;
+        let mut entries = self.inner.borrow_mut();
+
+        if let Some(e) = entries.get(&S::CATEGORY) {
+            e.period.set(std::cmp::min(e.period.get(), millis));
+            e.deadline.set(std::cmp::min(e.deadline.get(), deadline_millis));
+            return e.latest
+                .downcast_ref::<Latest<S>>()
+                .expect("a category can only have one sample type")
+                .clone();
+        }
+
+        let (send, recv) = watch::channel(None);
+        let period = Rc::new(Cell::new(millis));
+        let deadline = Rc::new(Cell::new(deadline_millis));
+        let wake = Rc::new(Notify::new());
+        tokio::task::spawn_local(sample_loop::<S>(send, period.clone(), deadline.clone(), wake.clone(), self.stats.clone(), Rc::downgrade(&self.inner)));
+
+        entries.insert(S::CATEGORY, Entry {
+            latest: Box::new(recv.clone()),
+            period,
+            deadline,
+            wake,
+        });
+        recv
+    }
+
+    // take a new sample right away instead of at the end of the period
+    pub fn resample(&self, cat: Category) {
+        if let Some(e) = self.inner.borrow().get(&cat) {
+            e.wake.notify();
+        }
+    }
+}
+
+// runs until every `Sampler` handle is dropped
+async fn sample_loop<S: Sample>(
+    send: watch::Sender<Option<Rc<S>>>,
+    period: Rc<Cell<u64>>,
+    deadline: Rc<Cell<u64>>,
+    wake: Rc<Notify>,
+    stats: Stats,
+    owner: Weak<Entries>
+)
+{
+    let mut sys = None;
+    let mut last = Instant::now();
+    loop {
+        let now = Instant::now();
+        let elapsed = now - last;
+        last = now;
+        let mut late = false;
+
+        let mut job = tokio::task::spawn_blocking(move || {
+            let mut sys = sys.unwrap_or_else(|| {
+                let mut sys = System::new();
+                S::init(&mut sys);
+                sys
+            });
+            let sample = S::take(&mut sys, elapsed);
+            (sys, sample)
+        });
+        // a slow refresh can't be cancelled, so its generators are
+        // shown as stale until it is done
+        let res = match timeout(Duration::from_millis(deadline.get()), &mut job).await {
+            Ok(res) => res,
+            Err(_) => {
+                late = true;
+                log::warn!("refreshing {:?} takes longer than {}ms", S::CATEGORY, deadline.get());
+                if send.broadcast(None).is_err() {
+                    break;
+                }
+                job.await
+            }
+        };
+        stats.refresh(S::CATEGORY.name(), now.elapsed(), late);
+        let sample = match res {
+            Ok((s, sample)) => {
+                sys = Some(s);
+                sample
+            },
+            Err(e) => {
+                log::error!("refreshing {:?} failed '{}'", S::CATEGORY, e);
+                break;
+            }
+        };
+
+        if send.broadcast(Some(Rc::new(sample))).is_err() {
+            break;
+        }
+
+        select! {
+            _ = delay_for(Duration::from_millis(period.get())) => (),
+            _ = wake.notified() => ()
+        }
+
+        if owner.upgrade().is_none() {
+            break;
+        }
+    }
+    log::debug!("stopped sampling {:?}", S::CATEGORY);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use crate::tasks::run_local;
+
+    static DELAY: AtomicU64 = AtomicU64::new(0);
+
+    // counts its refreshes, which take `DELAY` ms
+    struct Slow(u64);
+
+    impl Sample for Slow {
+        const CATEGORY: Category = Category::Components;
+
+        fn take(_sys: &mut System, _elapsed: Duration) -> Self {
+            static COUNT: AtomicU64 = AtomicU64::new(0);
+            std::thread::sleep(Duration::from_millis(DELAY.load(Ordering::SeqCst)));
+            Slow(COUNT.fetch_add(1, Ordering::SeqCst))
+        }
+    }
+
+    #[test]
+    fn slow_refreshes_are_stale_and_dont_block() {
+        run_local(async {
+            let sampler = Sampler::new(Stats::new());
+            let mut latest = sampler.subscribe::<Slow>(Duration::from_millis(20), Duration::from_millis(100));
+            while latest.recv().await.unwrap().is_none() {}
+
+            DELAY.store(400, Ordering::SeqCst);
+            let mut seen = Vec::new();
+            let started = Instant::now();
+            // the local set keeps running while the refresh blocks
+            delay_for(Duration::from_millis(200)).await;
+            assert!(started.elapsed() < Duration::from_millis(350));
+            seen.push(latest.borrow().as_ref().map(|s| s.0));
+
+            DELAY.store(0, Ordering::SeqCst);
+            loop {
+                let s = latest.recv().await.unwrap();
+                if let Some(s) = s {
+                    seen.push(Some(s.0));
+                    break;
+                }
+            }
+            assert_eq!(seen[0], None);
+            assert!(seen[1].is_some());
+        });
+    }
+}
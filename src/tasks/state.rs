@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tokio;
+use tokio::select;
+use tokio::sync::oneshot;
+use tokio::time::{self, Duration};
+use crate::tasks::ExitReason;
+
+const WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Inner {
+    path: Option<PathBuf>,
+    states: HashMap<String, String>,
+    dirty: bool,
+}
+
+// Small pieces of state keyed by generator name. The file has one
+// generator per line, as `name state`, with spaces and backslashes in
+// the name escaped by a backslash.
+#[derive(Clone)]
+pub struct StateStore {
+    inner: Rc<RefCell<Inner>>,
+}
+
+// $XDG_STATE_HOME/statusbar/state
+fn state_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(s) if !s.is_empty() => PathBuf::from(s),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("statusbar").join("state"))
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace(' ', "\\ ")
+}
+
+// splits a line at the first unescaped space
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let mut name = String::new();
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => name.push(chars.next()?.1),
+            ' ' => return Some((name, &line[i+1..])),
+            c => name.push(c),
+        }
+    }
+    None
+}
+
+impl StateStore {
+    // a store that never reads nor writes anything
+    pub fn disabled() -> Self {
+        StateStore {
+            inner: Rc::new(RefCell::new(Inner {
+                path: None,
+                states: HashMap::new(),
+                dirty: false,
+            })),
+        }
+    }
+
+    pub fn load() -> Self {
+        let path = match state_path() {
+            Some(p) => p,
+            None => {
+                log::warn!("couldn't find where to save state");
+                return Self::disabled();
+            }
+        };
+
+        let mut states = HashMap::new();
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines() {
+                    if let Some((name, state)) = split_line(line) {
+                        states.insert(name, state.to_string());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => log::warn!("couldn't read state from {:?} because '{}'", path, e),
+        }
+
+        StateStore {
+            inner: Rc::new(RefCell::new(Inner {
+                path: Some(path),
+                states,
+                dirty: false,
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.borrow().path.is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.inner.borrow().states.get(name).cloned()
+    }
+
+    // `None` forgets the state of `name`
+    pub fn set(&self, name: &str, state: Option<String>) {
+        let mut inner = self.inner.borrow_mut();
+        let changed = match state {
+            Some(s) => {
+                let s = s.replace('\n', " ");
+                inner.states.insert(name.to_string(), s.clone()) != Some(s)
+            }
+            None => inner.states.remove(name).is_some(),
+        };
+        inner.dirty |= changed;
+    }
+
+    // writes the file if anything has changed since last time
+    pub fn write(&self) -> io::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        let path = match &inner.path {
+            Some(p) if inner.dirty => p.clone(),
+            _ => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = String::new();
+        for (name, state) in inner.states.iter() {
+            content.push_str(&escape(name));
+            content.push(' ');
+            content.push_str(state);
+            content.push('\n');
+        }
+
+        // write to a temporary file first so a crash can't leave a
+        // half written file behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &path)?;
+        inner.dirty = false;
+        Ok(())
+    }
+}
+
+pub async fn state_writer(store: StateStore, mut shutdown: oneshot::Receiver<()>) -> ExitReason {
+    loop {
+        select! {
+            _ = time::delay_for(WRITE_INTERVAL) => (),
+            _ = &mut shutdown => break ExitReason::Normal
+        }
+
+        if let Err(e) = store.write() {
+            log::warn!("couldn't save state because '{}'", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let dir = std::env::temp_dir().join(format!("statusbar-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        std::env::set_var("XDG_STATE_HOME", &dir);
+        let path = dir.join("statusbar").join("state");
+
+        let store = StateStore::load();
+        assert!(store.is_enabled());
+        store.set("NET-0", Some("wlan0".to_string()));
+        store.set("my disk", Some("/ and /home".to_string()));
+        store.set("back\\slash", Some("two\nlines".to_string()));
+        store.set("gone", Some("soon".to_string()));
+        store.set("gone", None);
+
+        // a crash in the middle of an earlier write
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path.with_extension("tmp"), "half a li").unwrap();
+        store.write().unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded = StateStore::load();
+        assert_eq!(loaded.get("NET-0").as_deref(), Some("wlan0"));
+        assert_eq!(loaded.get("my disk").as_deref(), Some("/ and /home"));
+        assert_eq!(loaded.get("back\\slash").as_deref(), Some("two lines"));
+        assert_eq!(loaded.get("gone"), None);
+
+        // nothing changed, so nothing is written
+        fs::remove_file(&path).unwrap();
+        loaded.write().unwrap();
+        assert!(!path.exists());
+
+        std::env::remove_var("XDG_STATE_HOME");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use nix::unistd::{sysconf, SysconfVar};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// shortest time cpu usage is measured over
+const USAGE_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Default,Clone)]
+pub struct GenStats {
+    pub messages: u64,
+    // messages that didn't fit in the generator's queue
+    pub dropped: u64,
+    pub updates: u64,
+    pub update_time: Duration,
+    pub max_update: Duration,
+    // child processes started again
+    pub restarts: u64,
+}
+
+// refreshes of one sampler category, shared by its generators
+#[derive(Default,Clone)]
+pub struct RefreshStats {
+    pub refreshes: u64,
+    pub refresh_time: Duration,
+    pub max_refresh: Duration,
+    // refreshes that took longer than their deadline
+    pub late: u64,
+}
+
+#[derive(Default,Clone)]
+pub struct BarStats {
+    pub redraws: u64,
+    // control messages the bar fell behind on
+    pub lagged: u64,
+    pub tray_restarts: u64,
+}
+
+// cpu time and resident memory of this process
+#[derive(Default,Clone,Copy)]
+pub struct Usage {
+    pub cpu_percent: f64,
+    pub rss: u64,
+}
+
+struct Inner {
+    started: Instant,
+    gens: BTreeMap<String, GenStats>,
+    bars: BTreeMap<String, BarStats>,
+    refreshes: BTreeMap<&'static str, RefreshStats>,
+    // cpu ticks at some earlier time
+    last_cpu: Option<(Instant, u64)>,
+    usage: Usage,
+}
+
+// Counters about the bar itself, to find out which segment is expensive.
+#[derive(Clone)]
+pub struct Stats {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            inner: Rc::new(RefCell::new(Inner {
+                started: Instant::now(),
+                gens: BTreeMap::new(),
+                bars: BTreeMap::new(),
+                refreshes: BTreeMap::new(),
+                last_cpu: None,
+                usage: Usage::default(),
+            })),
+        }
+    }
+
+    fn with_gen<F: FnOnce(&mut GenStats)>(&self, name: &str, f: F) {
+        let mut inner = self.inner.borrow_mut();
+        match inner.gens.get_mut(name) {
+            Some(g) => f(g),
+            None => f(inner.gens.entry(name.to_string()).or_default()),
+        }
+    }
+
+    fn with_bar<F: FnOnce(&mut BarStats)>(&self, bar: &str, f: F) {
+        let mut inner = self.inner.borrow_mut();
+        match inner.bars.get_mut(bar) {
+            Some(b) => f(b),
+            None => f(inner.bars.entry(bar.to_string()).or_default()),
+        }
+    }
+
+    pub fn message(&self, gen: &str) {
+        self.with_gen(gen, |g| g.messages += 1);
+    }
+
+    pub fn dropped(&self, gen: &str) {
+        self.with_gen(gen, |g| g.dropped += 1);
+    }
+
+    pub fn update(&self, gen: &str, took: Duration) {
+        self.with_gen(gen, |g| {
+            g.updates += 1;
+            g.update_time += took;
+            g.max_update = std::cmp::max(g.max_update, took);
+        });
+    }
+
+    pub fn refresh(&self, category: &'static str, took: Duration, late: bool) {
+        let mut inner = self.inner.borrow_mut();
+        let r = inner.refreshes.entry(category).or_default();
+        r.refreshes += 1;
+        r.refresh_time += took;
+        r.max_refresh = std::cmp::max(r.max_refresh, took);
+        if late {
+            r.late += 1;
+        }
+    }
+
+    pub fn restart(&self, gen: &str) {
+        self.with_gen(gen, |g| g.restarts += 1);
+    }
+
+    pub fn redraw(&self, bar: &str) {
+        self.with_bar(bar, |b| b.redraws += 1);
+    }
+
+    pub fn lagged(&self, bar: &str, n: u64) {
+        self.with_bar(bar, |b| b.lagged += n);
+    }
+
+    pub fn tray_restart(&self, bar: &str) {
+        self.with_bar(bar, |b| b.tray_restarts += 1);
+    }
+
+    // the generator that has spent the most time updating
+    pub fn most_expensive(&self) -> Option<(String, GenStats)> {
+        self.inner.borrow().gens
+            .iter()
+            .max_by_key(|(_, g)| g.update_time)
+            .map(|(n, g)| (n.clone(), g.clone()))
+    }
+
+    // Cpu usage over at least `USAGE_WINDOW`, or since start at first.
+    // Everyone asking within a window gets the same answer, so that
+    // callers don't shorten each other's windows.
+    pub fn usage(&self) -> Usage {
+        self.usage_at(Instant::now())
+    }
+
+    fn usage_at(&self, now: Instant) -> Usage {
+        let mut inner = self.inner.borrow_mut();
+        let fresh = matches!(inner.last_cpu, Some((then, _)) if now - then < USAGE_WINDOW);
+        if fresh {
+            return inner.usage;
+        }
+        if let Some(ticks) = cpu_ticks() {
+            let (then, old) = inner.last_cpu.unwrap_or((inner.started, 0));
+            let secs = (now - then).as_secs_f64();
+            let tck = sysconf(SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as f64;
+            if secs > 0.0 {
+                inner.usage.cpu_percent = (ticks.saturating_sub(old) as f64 / tck) / secs * 100.0;
+            }
+            inner.last_cpu = Some((now, ticks));
+        }
+        if let Some(rss) = rss() {
+            inner.usage.rss = rss;
+        }
+        inner.usage
+    }
+
+    pub fn report(&self) -> String {
+        let usage = self.usage();
+        let inner = self.inner.borrow();
+        let mut s = String::new();
+        let _ = writeln!(s, "uptime {}s, cpu {:.1}%, rss {} KiB",
+                         inner.started.elapsed().as_secs(), usage.cpu_percent, usage.rss / 1024);
+        for (name, b) in inner.bars.iter() {
+            let _ = writeln!(s, "bar {}: {} redraws, {} lagged, {} tray restarts",
+                             name, b.redraws, b.lagged, b.tray_restarts);
+        }
+        for (name, g) in inner.gens.iter() {
+            let avg = if g.updates > 0 {g.update_time / g.updates as u32} else {Duration::from_secs(0)};
+            let _ = writeln!(s, "generator {}: {} messages, {} dropped, {} updates (avg {:?}, max {:?}, total {:?}), {} restarts",
+                             name, g.messages, g.dropped, g.updates, avg, g.max_update, g.update_time, g.restarts);
+        }
+        for (cat, r) in inner.refreshes.iter() {
+            let avg = if r.refreshes > 0 {r.refresh_time / r.refreshes as u32} else {Duration::from_secs(0)};
+            let _ = writeln!(s, "sampler {}: {} refreshes (avg {:?}, max {:?}, total {:?}), {} late",
+                             cat, r.refreshes, avg, r.max_refresh, r.refresh_time, r.late);
+        }
+        s
+    }
+}
+
+// utime + stime from /proc/self/stat
+fn cpu_ticks() -> Option<u64> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // the name in parenthesis can contain spaces
+    let rest = &stat[stat.rfind(')')? + 2..];
+    let fields: Vec<&str> = rest.split(' ').collect();
+    // utime and stime are the 14th and 15th fields, counting pid and name
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+// resident memory in bytes from /proc/self/statm
+fn rss() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split(' ').nth(1)?.parse().ok()?;
+    let page_size = sysconf(SysconfVar::PAGE_SIZE).ok().flatten()? as u64;
+    Some(pages * page_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_window_is_shared() {
+        let stats = Stats::new();
+        let start = Instant::now();
+        stats.usage_at(start);
+        let first = stats.inner.borrow().last_cpu;
+        assert!(first.is_some());
+
+        // another client asking soon after doesn't start a new window
+        stats.usage_at(start + Duration::from_secs(1));
+        assert_eq!(stats.inner.borrow().last_cpu, first);
+
+        stats.usage_at(start + USAGE_WINDOW);
+        assert_ne!(stats.inner.borrow().last_cpu, first);
+    }
+
+    #[test]
+    fn refreshes_are_counted_per_category() {
+        let stats = Stats::new();
+        stats.refresh("cpu", Duration::from_millis(10), false);
+        stats.refresh("cpu", Duration::from_millis(30), true);
+        stats.refresh("disks", Duration::from_millis(5), false);
+        let report = stats.report();
+        assert!(report.contains("sampler cpu: 2 refreshes (avg 20ms, max 30ms, total 40ms), 1 late"), "{}", report);
+        assert!(report.contains("sampler disks: 1 refreshes"), "{}", report);
+    }
+}
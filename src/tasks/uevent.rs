@@ -0,0 +1,301 @@
+use nix::libc;
+use nix::sys::socket::{bind, recvfrom, SockAddr};
+use nix::unistd::close;
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+use mio::unix::EventedFd;
+use tokio;
+use tokio::io::{AsyncRead, AsyncReadExt, PollEvented};
+use tokio::select;
+use tokio::sync::{mpsc,oneshot};
+use crate::tasks::ExitReason;
+
+// the multicast group where the kernel sends its events, udev
+// rebroadcasts them on group 2
+const KERNEL_GROUP: u32 = 1;
+const BUF_SIZE: usize = 8192;
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub struct UEvent {
+    pub action: String,
+    pub subsystem: String,
+    pub devpath: String,
+}
+
+impl UEvent {
+    // A kernel uevent looks like `add@/devices/...\0ACTION=add\0DEVPATH=/devices/...\0SUBSYSTEM=net\0...`
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let mut action = None;
+        let mut subsystem = None;
+        let mut devpath = None;
+
+        for field in buf.split(|b| *b == 0) {
+            let field = match std::str::from_utf8(field) {
+                Ok(f) => f,
+                Err(_) => continue,
+            };
+            let (key, val) = match field.find('=') {
+                Some(i) => (&field[..i], &field[i+1..]),
+                None => continue,
+            };
+            match key {
+                "ACTION" => action = Some(val.to_string()),
+                "SUBSYSTEM" => subsystem = Some(val.to_string()),
+                "DEVPATH" => devpath = Some(val.to_string()),
+                _ => (),
+            }
+        }
+
+        Some(UEvent {
+            action: action?,
+            subsystem: subsystem?,
+            devpath: devpath?,
+        })
+    }
+
+    // the message generators receive
+    pub fn to_msg(&self) -> String {
+        format!("uevent {} {}", self.subsystem, self.action)
+    }
+}
+
+// generator names keyed by the subsystems they are interested in
+pub type Subscribers = HashMap<&'static str, Vec<String>>;
+
+// the lines to send to pipo for an event
+pub fn route(ev: &UEvent, subs: &Subscribers) -> Vec<String> {
+    subs.get(ev.subsystem.as_str())
+        .map(|names| names.iter()
+             .map(|n| format!("{} {}", n, ev.to_msg()))
+             .collect())
+        .unwrap_or_default()
+}
+
+struct UEventSocket(RawFd);
+
+impl UEventSocket {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK,
+                         libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                         libc::NETLINK_KOBJECT_UEVENT)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sock = UEventSocket(fd);
+        bind(sock.0, &SockAddr::new_netlink(0, KERNEL_GROUP))
+            .map_err(io::Error::other)?;
+        Ok(sock)
+    }
+}
+
+impl io::Read for UEventSocket {
+    // only reads what the kernel sent, other processes can send to the
+    // group too
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let (len, from) = recvfrom(self.0, buf)
+                .map_err(|e| match e.as_errno() {
+                    Some(errno) => io::Error::from_raw_os_error(errno as i32),
+                    None => io::Error::other(e),
+                })?;
+            match from {
+                Some(SockAddr::Netlink(nl)) if nl.pid() != 0 => {
+                    log::debug!("ignoring uevent from pid {}", nl.pid());
+                },
+                _ => return Ok(len),
+            }
+        }
+    }
+}
+
+impl Evented for UEventSocket {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.0).deregister(poll)
+    }
+}
+
+impl std::ops::Drop for UEventSocket {
+    fn drop(&mut self) {
+        if let Err(e) = close(self.0) {
+            log::warn!("couldn't close uevent socket '{}'", e);
+        }
+    }
+}
+
+// Reads one uevent per read from `source` and forwards it to everyone
+// in `subs`. Stops when `to_pipo` is closed or `source` fails.
+pub async fn listen<R>(mut source: R, subs: Subscribers, mut to_pipo: mpsc::Sender<String>) -> ExitReason
+where R: AsyncRead + Unpin
+{
+    let mut buf = vec![0; BUF_SIZE];
+    loop {
+        let len = match source.read(&mut buf).await {
+            Ok(0) => break ExitReason::Normal,
+            Ok(l) => l,
+            // a burst of events overflowed the socket, we only miss those
+            Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                log::warn!("missed some uevents '{}'", e);
+                continue;
+            },
+            Err(e) => {
+                log::error!("couldn't read uevent '{}'", e);
+                break ExitReason::NonFatal;
+            }
+        };
+
+        let ev = match UEvent::parse(&buf[..len]) {
+            Some(ev) => ev,
+            None => {
+                log::debug!("ignoring malformed uevent");
+                continue;
+            }
+        };
+
+        for line in route(&ev, &subs) {
+            if to_pipo.send(line).await.is_err() {
+                return ExitReason::Normal;
+            }
+        }
+    }
+}
+
+pub async fn uevent_listener(
+    subs: Subscribers,
+    to_pipo: mpsc::Sender<String>,
+    shutdown: oneshot::Receiver<()>
+) -> ExitReason
+{
+    let sock = match UEventSocket::new().and_then(PollEvented::new) {
+        Ok(s) => s,
+        Err(e) => {
+            log::warn!("couldn't listen for uevents '{}'", e);
+            return ExitReason::NonFatal;
+        }
+    };
+
+    select! {
+        r = listen(sock, subs, to_pipo) => r,
+        _ = shutdown => ExitReason::Normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    const ADD_NET: &[u8] = b"add@/devices/virtual/net/wg0\0ACTION=add\0DEVPATH=/devices/virtual/net/wg0\0SUBSYSTEM=net\0INTERFACE=wg0\0IFINDEX=7\0SEQNUM=4242\0";
+
+    // gives one datagram, or error, per read, like the netlink socket
+    struct Datagrams(VecDeque<io::Result<Vec<u8>>>);
+
+    impl AsyncRead for Datagrams {
+        fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            let d = match self.0.pop_front() {
+                Some(d) => d?,
+                None => return Poll::Ready(Ok(0)),
+            };
+            buf[..d.len()].copy_from_slice(&d);
+            Poll::Ready(Ok(d.len()))
+        }
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<String>) -> Vec<String> {
+        let mut lines = vec![];
+        while let Ok(l) = rx.try_recv() {
+            lines.push(l);
+        }
+        lines
+    }
+
+    fn subscribers() -> Subscribers {
+        let mut subs = Subscribers::new();
+        subs.insert("net", vec!["NET-0".to_string(), "IP-1".to_string()]);
+        subs.insert("power_supply", vec!["BAT-0".to_string()]);
+        subs
+    }
+
+    #[test]
+    fn parses_kernel_events() {
+        assert_eq!(UEvent::parse(ADD_NET), Some(UEvent {
+            action: "add".to_string(),
+            subsystem: "net".to_string(),
+            devpath: "/devices/virtual/net/wg0".to_string(),
+        }));
+    }
+
+    #[test]
+    fn values_can_contain_equals() {
+        let ev = UEvent::parse(b"ACTION=change\0DEVPATH=/a=b\0SUBSYSTEM=power_supply").unwrap();
+        assert_eq!(ev.devpath, "/a=b");
+        assert_eq!(ev.subsystem, "power_supply");
+    }
+
+    #[test]
+    fn rejects_incomplete_events() {
+        assert_eq!(UEvent::parse(b""), None);
+        assert_eq!(UEvent::parse(b"add@/devices/x\0ACTION=add\0DEVPATH=/devices/x\0"), None);
+        // the header alone isn't enough
+        assert_eq!(UEvent::parse(b"remove@/devices/x"), None);
+    }
+
+    #[test]
+    fn skips_fields_that_arent_utf8() {
+        let ev = UEvent::parse(b"ACTION=remove\0NAME=\xff\xfe\0DEVPATH=/d\0SUBSYSTEM=net\0").unwrap();
+        assert_eq!(ev.action, "remove");
+        assert_eq!(ev.devpath, "/d");
+    }
+
+    #[test]
+    fn routes_to_every_subscriber() {
+        let ev = UEvent::parse(ADD_NET).unwrap();
+        assert_eq!(route(&ev, &subscribers()),
+                   vec!["NET-0 uevent net add", "IP-1 uevent net add"]);
+
+        let ev = UEvent::parse(b"ACTION=add\0DEVPATH=/d\0SUBSYSTEM=block\0").unwrap();
+        assert!(route(&ev, &subscribers()).is_empty());
+    }
+
+    #[test]
+    fn listen_forwards_and_skips_malformed() {
+        let source = Datagrams(vec![
+            Ok(b"garbage".to_vec()),
+            Ok(ADD_NET.to_vec()),
+            Ok(b"ACTION=change\0DEVPATH=/bat\0SUBSYSTEM=power_supply\0".to_vec()),
+        ].into());
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let reason = crate::tasks::run_local(listen(source, subscribers(), tx));
+        assert!(reason.is_normal());
+        assert_eq!(drain(&mut rx), vec!["NET-0 uevent net add", "IP-1 uevent net add", "BAT-0 uevent power_supply change"]);
+    }
+
+    #[test]
+    fn listen_survives_overflows() {
+        let source = Datagrams(vec![
+            Err(io::Error::from_raw_os_error(libc::ENOBUFS)),
+            Ok(ADD_NET.to_vec()),
+            Err(io::Error::from_raw_os_error(libc::EBADF)),
+            Ok(ADD_NET.to_vec()),
+        ].into());
+        let (tx, mut rx) = mpsc::channel(8);
+
+        let reason = crate::tasks::run_local(listen(source, subscribers(), tx));
+        assert!(reason.is_non_fatal());
+        assert_eq!(drain(&mut rx), vec!["NET-0 uevent net add", "IP-1 uevent net add"]);
+    }
+}